@@ -0,0 +1,30 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{write_atomic, Error, Result};
+
+// Alias to config name
+pub type Aliases = BTreeMap<String, String>;
+
+pub fn path(kubenv_dir: &Path) -> PathBuf {
+    return kubenv_dir.join(".aliases");
+}
+
+pub fn load(kubenv_dir: &Path) -> Aliases {
+    let content = match fs::read_to_string(path(kubenv_dir)) {
+        Ok(c) => c,
+        Err(_) => return Aliases::new(),
+    };
+
+    return toml::from_str(&content).unwrap_or_default();
+}
+
+pub fn save(kubenv_dir: &Path, aliases: &Aliases) -> Result {
+    let aliases_path = path(kubenv_dir);
+    let content = match toml::to_string(aliases) {
+        Ok(c) => c,
+        Err(msg) => return Err(Error::Metadata(msg.to_string())),
+    };
+    return write_atomic(&aliases_path, content.as_bytes());
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::{Error, ProgressWriter, Result};
+
+// Files are stored under their path relative to the kubenv directory,
+// `progress` receives the number of compressed bytes written so far
+pub fn write<F: FnMut(u64)>(
+    dest: &Path,
+    base_dir: &Path,
+    files: &[PathBuf],
+    progress: F,
+) -> Result {
+    let file = match fs::File::create(dest) {
+        Ok(f) => f,
+        Err(msg) => return Err(Error::io("Cannot create archive", dest, msg)),
+    };
+    let writer = ProgressWriter::new(file, progress);
+    let mut builder = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+    for path in files {
+        let name = match path.strip_prefix(base_dir) {
+            Ok(n) => n,
+            Err(_) => continue,
+        };
+        if let Err(msg) = builder.append_path_with_name(path, name) {
+            return Err(Error::io("Cannot add file to archive", path, msg));
+        }
+    }
+    let result = builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .and_then(|writer| writer.into_inner().sync_all());
+    if let Err(msg) = result {
+        return Err(Error::io("Cannot write archive", dest, msg));
+    }
+
+    return Ok(());
+}
+
+// Calls `extract` with the relative path of every regular file; the entry is
+// unpacked into `base_dir` when it returns true
+pub fn read<F>(src: &Path, base_dir: &Path, mut extract: F) -> Result
+where
+    F: FnMut(&Path) -> bool,
+{
+    let file = match fs::File::open(src) {
+        Ok(f) => f,
+        Err(msg) => return Err(Error::io("Cannot open archive", src, msg)),
+    };
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let entries = match archive.entries() {
+        Ok(e) => e,
+        Err(msg) => return Err(Error::io("Cannot read archive", src, msg)),
+    };
+    for entry in entries {
+        let mut entry = match entry {
+            Ok(e) => e,
+            Err(msg) => return Err(Error::io("Cannot read archive", src, msg)),
+        };
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = match entry.path() {
+            Ok(p) => p.into_owned(),
+            Err(msg) => return Err(Error::io("Cannot read archive", src, msg)),
+        };
+        if !extract(&path) {
+            continue;
+        }
+        // `unpack_in` refuses entries escaping the base directory
+        if let Err(msg) = entry.unpack_in(base_dir) {
+            return Err(Error::io("Cannot extract file", base_dir.join(&path), msg));
+        }
+    }
+
+    return Ok(());
+}
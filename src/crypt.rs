@@ -0,0 +1,97 @@
+use std::io::Read;
+use std::path::Path;
+
+use crate::{Error, Result};
+
+pub const SUFFIX: &str = ".age";
+
+pub fn is_encrypted(path: &Path) -> bool {
+    return match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name.ends_with(SUFFIX),
+        None => false,
+    };
+}
+
+// An age X25519 identity, configs are encrypted to its own public key
+#[cfg(feature = "age")]
+pub struct Key(age::x25519::Identity);
+
+#[cfg(not(feature = "age"))]
+pub enum Key {}
+
+// Key files written by `age-keygen` hold comments next to the key itself
+#[cfg(feature = "age")]
+pub fn parse_key(value: &str) -> Result<Key> {
+    let line = value
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .unwrap_or_default();
+
+    return match line.parse::<age::x25519::Identity>() {
+        Ok(identity) => Ok(Key(identity)),
+        Err(msg) => Err(Error::Key(String::from(msg))),
+    };
+}
+
+#[cfg(feature = "age")]
+pub fn encrypt(content: &[u8], key: &Key) -> Result<Vec<u8>> {
+    use std::io::Write;
+
+    let encryptor = match age::Encryptor::with_recipients(vec![Box::new(key.0.to_public())]) {
+        Some(e) => e,
+        None => return Err(Error::Key(String::from("no recipients"))),
+    };
+    let mut encrypted: Vec<u8> = Vec::new();
+    let mut writer = match encryptor.wrap_output(&mut encrypted) {
+        Ok(w) => w,
+        Err(msg) => return Err(Error::Key(msg.to_string())),
+    };
+    if let Err(msg) = writer.write_all(content).and_then(|_| writer.finish()) {
+        return Err(Error::stream("Cannot encrypt content", msg));
+    }
+
+    return Ok(encrypted);
+}
+
+#[cfg(feature = "age")]
+pub fn decrypter<R: Read + 'static>(reader: R, key: &Key, path: &Path) -> Result<Box<dyn Read>> {
+    let decryptor = match age::Decryptor::new(reader) {
+        Ok(age::Decryptor::Recipients(d)) => d,
+        Ok(age::Decryptor::Passphrase(_)) => {
+            return Err(Error::Decrypt {
+                path: path.to_path_buf(),
+                message: String::from("passphrase encryption is not supported"),
+            })
+        }
+        Err(msg) => {
+            return Err(Error::Decrypt {
+                path: path.to_path_buf(),
+                message: msg.to_string(),
+            })
+        }
+    };
+
+    return match decryptor.decrypt(std::iter::once(&key.0 as &dyn age::Identity)) {
+        Ok(r) => Ok(Box::new(r)),
+        Err(msg) => Err(Error::Decrypt {
+            path: path.to_path_buf(),
+            message: msg.to_string(),
+        }),
+    };
+}
+
+#[cfg(not(feature = "age"))]
+pub fn parse_key(_value: &str) -> Result<Key> {
+    return Err(Error::FeatureDisabled(String::from("age")));
+}
+
+#[cfg(not(feature = "age"))]
+pub fn encrypt(_content: &[u8], key: &Key) -> Result<Vec<u8>> {
+    match *key {}
+}
+
+#[cfg(not(feature = "age"))]
+pub fn decrypter<R: Read + 'static>(_reader: R, key: &Key, _path: &Path) -> Result<Box<dyn Read>> {
+    match *key {}
+}
@@ -0,0 +1,267 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::result;
+
+pub type Result<T = ()> = result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    HomeDirNotFound,
+    KubenvDirNotFound(PathBuf),
+    Io {
+        message: String,
+        path: PathBuf,
+        source: io::Error,
+    },
+    Stream {
+        message: String,
+        source: io::Error,
+    },
+    ConfigNotFound(String),
+    HashNotFound(String),
+    AliasNotFound(String),
+    AmbiguousHash {
+        prefix: String,
+        candidates: Vec<String>,
+    },
+    DuplicateName(String),
+    DuplicateHash(String),
+    AlreadyApplied(String),
+    UnmanagedConfig(PathBuf),
+    InvalidName {
+        name: String,
+        reason: String,
+    },
+    NotManaged(String),
+    // The live entry stands for the config file itself
+    ApplyLive(String),
+    FileExists(PathBuf),
+    Yaml(serde_yaml::Error),
+    Validation(String),
+    MultipleKubeconfigPaths(String),
+    Metadata(String),
+    BackupNotFound(PathBuf),
+    MergeConflict {
+        context: String,
+        first: String,
+        second: String,
+    },
+    EntryConflict {
+        kind: String,
+        name: String,
+        first: String,
+        second: String,
+    },
+    Editor {
+        editor: String,
+        status: ExitStatus,
+    },
+    Http {
+        url: String,
+        message: String,
+    },
+    FeatureDisabled(String),
+    Busy(PathBuf),
+    Watch(String),
+    NotInteractive,
+    NoInput,
+    NoPreviousConfig,
+    ContextNotFound(String),
+    Compressed {
+        name: String,
+        action: String,
+    },
+    HashMismatch {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+    Encrypted {
+        name: String,
+        action: String,
+    },
+    Key(String),
+    KeyRequired(PathBuf),
+    ConfigFile {
+        path: PathBuf,
+        message: String,
+    },
+    Decrypt {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl Error {
+    pub fn io<P: Into<PathBuf>>(message: &str, path: P, source: io::Error) -> Self {
+        return Self::Io {
+            message: String::from(message),
+            path: path.into(),
+            source,
+        };
+    }
+
+    pub fn stream(message: &str, source: io::Error) -> Self {
+        return Self::Stream {
+            message: String::from(message),
+            source,
+        };
+    }
+
+    pub fn invalid_name(name: &str, reason: &str) -> Self {
+        return Self::InvalidName {
+            name: String::from(name),
+            reason: String::from(reason),
+        };
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Self::HomeDirNotFound => write!(f, "Cannot get home directory"),
+            Self::KubenvDirNotFound(path) => {
+                write!(f, "Cannot find kubenv directory '{}'", path.display())
+            }
+            Self::Io {
+                message,
+                path,
+                source,
+            } => write!(f, "{} '{}': {}", message, path.display(), source),
+            Self::Stream { message, source } => write!(f, "{}: {}", message, source),
+            Self::ConfigNotFound(name) => write!(f, "Cannot find config with name '{}'", name),
+            Self::AliasNotFound(alias) => write!(f, "Cannot find alias '{}'", alias),
+            Self::HashNotFound(hash) => write!(f, "Cannot find config with hash '{}'", hash),
+            Self::AmbiguousHash { prefix, candidates } => write!(
+                f,
+                "Hash prefix '{}' matches several configs: {}",
+                prefix,
+                candidates.join(", "),
+            ),
+            Self::DuplicateName(name) => write!(f, "Config with name '{}' already exists", name),
+            Self::DuplicateHash(name) => write!(f, "Config already exists with name '{}'", name),
+            Self::AlreadyApplied(name) => write!(f, "Config '{}' already applied", name),
+            Self::UnmanagedConfig(path) => write!(
+                f,
+                "Config file '{}' is not managed by kubenv and would be overwritten",
+                path.display(),
+            ),
+            Self::InvalidName { name, reason } => {
+                write!(f, "Invalid config name '{}': {}", name, reason)
+            }
+            Self::NotManaged(name) => write!(f, "Config '{}' is not managed by kubenv", name),
+            Self::ApplyLive(name) => write!(
+                f,
+                "Config '{}' is the config file itself and cannot be applied",
+                name,
+            ),
+            Self::FileExists(path) => write!(f, "File '{}' already exists", path.display()),
+            Self::Yaml(source) => write!(f, "Cannot parse YAML: {}", source),
+            Self::Validation(message) => write!(f, "Invalid kubeconfig: {}", message),
+            Self::MultipleKubeconfigPaths(value) => write!(
+                f,
+                "KUBECONFIG contains multiple paths ('{}'), configs can only be applied to a single file",
+                value,
+            ),
+            Self::Metadata(message) => write!(f, "Cannot serialize metadata: {}", message),
+            Self::BackupNotFound(path) => {
+                write!(f, "Cannot find backup file '{}'", path.display())
+            }
+            Self::MergeConflict {
+                context,
+                first,
+                second,
+            } => write!(
+                f,
+                "Context '{}' is defined in both '{}' and '{}'",
+                context, first, second,
+            ),
+            Self::EntryConflict {
+                kind,
+                name,
+                first,
+                second,
+            } => write!(
+                f,
+                "{} '{}' is defined differently in '{}' and '{}'",
+                kind, name, first, second,
+            ),
+            Self::Editor { editor, status } => {
+                write!(f, "Editor '{}' failed with {}", editor, status)
+            }
+            Self::Http { url, message } => write!(f, "Cannot fetch '{}': {}", url, message),
+            Self::FeatureDisabled(feature) => {
+                write!(f, "kubenv was built without the '{}' feature", feature)
+            }
+            Self::NotInteractive => write!(
+                f,
+                "Cannot show the config picker without a terminal, pass a config name",
+            ),
+            Self::NoInput => write!(
+                f,
+                "Cannot read config from a terminal, pipe it to stdin or pass --file",
+            ),
+            Self::NoPreviousConfig => write!(
+                f,
+                "No previously applied config to go back to, apply at least two configs first",
+            ),
+            Self::ContextNotFound(name) => write!(f, "Cannot find context with name '{}'", name),
+            Self::Compressed { name, action } => write!(
+                f,
+                "Config '{}' is stored compressed and cannot be {}",
+                name, action,
+            ),
+            Self::HashMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Config '{}' has hash '{}', expected '{}'",
+                name, actual, expected,
+            ),
+            Self::Encrypted { name, action } => write!(
+                f,
+                "Config '{}' is stored encrypted and cannot be {}",
+                name, action,
+            ),
+            Self::Key(message) => write!(f, "Invalid age key: {}", message),
+            Self::KeyRequired(path) => write!(
+                f,
+                "Config file '{}' is encrypted, set KUBENV_KEY or KUBENV_KEY_FILE to the age key",
+                path.display(),
+            ),
+            Self::ConfigFile { path, message } => {
+                write!(
+                    f,
+                    "Cannot parse config file '{}': {}",
+                    path.display(),
+                    message
+                )
+            }
+            Self::Decrypt { path, message } => {
+                write!(f, "Cannot decrypt '{}': {}", path.display(), message)
+            }
+            Self::Watch(message) => write!(f, "Cannot watch for changes: {}", message),
+            Self::Busy(path) => write!(
+                f,
+                "Another kubenv process holds the lock '{}'",
+                path.display(),
+            ),
+        };
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        return match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Stream { source, .. } => Some(source),
+            Self::Yaml(source) => Some(source),
+            _ => None,
+        };
+    }
+}
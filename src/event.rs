@@ -0,0 +1,53 @@
+use std::time::SystemTime;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Apply,
+    Add,
+    Remove,
+    Rename,
+}
+
+impl Operation {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            Self::Apply => "apply",
+            Self::Add => "add",
+            Self::Remove => "remove",
+            Self::Rename => "rename",
+        };
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub operation: Operation,
+    pub name: String,
+    pub hash: String,
+    // The old name of a renamed config
+    pub from: Option<String>,
+    pub timestamp: SystemTime,
+}
+
+impl Event {
+    pub(crate) fn new(operation: Operation, name: &str, hash: &str) -> Self {
+        return Self {
+            operation,
+            name: String::from(name),
+            hash: String::from(hash),
+            from: None,
+            timestamp: SystemTime::now(),
+        };
+    }
+}
+
+// Receives an event after each successful change, how it is stored is up to the sink
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: &Event);
+}
+
+pub struct NoopSink;
+
+impl EventSink for NoopSink {
+    fn emit(&self, _event: &Event) {}
+}
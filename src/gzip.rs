@@ -0,0 +1,42 @@
+use std::io::Read;
+use std::path::Path;
+
+use crate::{Error, Result};
+
+pub const SUFFIX: &str = ".gz";
+
+pub fn is_compressed(path: &Path) -> bool {
+    return match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name.ends_with(SUFFIX),
+        None => false,
+    };
+}
+
+#[cfg(feature = "gzip")]
+pub fn compress(content: &[u8]) -> Result<Vec<u8>> {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    return match encoder.write_all(content).and_then(|_| encoder.finish()) {
+        Ok(compressed) => Ok(compressed),
+        Err(msg) => Err(Error::stream("Cannot compress content", msg)),
+    };
+}
+
+#[cfg(feature = "gzip")]
+pub fn decoder<R: Read + 'static>(reader: R) -> Result<Box<dyn Read>> {
+    return Ok(Box::new(flate2::read::GzDecoder::new(reader)));
+}
+
+#[cfg(not(feature = "gzip"))]
+pub fn compress(_content: &[u8]) -> Result<Vec<u8>> {
+    return Err(Error::FeatureDisabled(String::from("gzip")));
+}
+
+#[cfg(not(feature = "gzip"))]
+pub fn decoder<R: Read + 'static>(_reader: R) -> Result<Box<dyn Read>> {
+    return Err(Error::FeatureDisabled(String::from("gzip")));
+}
@@ -0,0 +1,94 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::result;
+use std::str::FromStr;
+
+use crate::{Error, Result};
+
+// Content identity of configs. Hash-prefix names and recorded hashes depend
+// on it, so it is chosen once when a `KubEnv` is built
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+            Self::Blake3 => "blake3",
+        };
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.as_str());
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(value: &str) -> result::Result<Self, Self::Err> {
+        return match value.to_ascii_lowercase().as_str() {
+            "sha256" => Ok(Self::Sha256),
+            "sha512" => Ok(Self::Sha512),
+            "blake3" => Ok(Self::Blake3),
+            _ => Err(format!(
+                "unknown hash algorithm '{}', expected sha256, sha512 or blake3",
+                value,
+            )),
+        };
+    }
+}
+
+pub fn digest(algorithm: HashAlgorithm, content: &[u8]) -> Result<String> {
+    return match algorithm {
+        HashAlgorithm::Sha256 => Ok(sha256::digest(content)),
+        HashAlgorithm::Sha512 => sha512(content),
+        HashAlgorithm::Blake3 => blake3(content),
+    };
+}
+
+pub fn file_digest(algorithm: HashAlgorithm, path: &Path) -> Result<String> {
+    if algorithm == HashAlgorithm::Sha256 {
+        return match sha256::try_digest(path) {
+            Ok(hash) => Ok(hash),
+            Err(msg) => Err(Error::io("Cannot get hash from file", path, msg)),
+        };
+    }
+    let content = match fs::read(path) {
+        Ok(c) => c,
+        Err(msg) => return Err(Error::io("Cannot get hash from file", path, msg)),
+    };
+
+    return digest(algorithm, &content);
+}
+
+#[cfg(feature = "sha512")]
+fn sha512(content: &[u8]) -> Result<String> {
+    use sha2::{Digest, Sha512};
+
+    return Ok(format!("{:x}", Sha512::digest(content)));
+}
+
+#[cfg(not(feature = "sha512"))]
+fn sha512(_content: &[u8]) -> Result<String> {
+    return Err(Error::FeatureDisabled(String::from("sha512")));
+}
+
+#[cfg(feature = "blake3")]
+fn blake3(content: &[u8]) -> Result<String> {
+    return Ok(blake3::hash(content).to_hex().to_string());
+}
+
+#[cfg(not(feature = "blake3"))]
+fn blake3(_content: &[u8]) -> Result<String> {
+    return Err(Error::FeatureDisabled(String::from("blake3")));
+}
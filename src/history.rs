@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{write_atomic, Error, Result};
+
+// Applies within the same second are told apart by the sequence number,
+// which grows with every record
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct Entry {
+    pub seq: u64,
+    // Seconds since the Unix epoch
+    pub applied: u64,
+}
+
+type History = BTreeMap<String, Entry>;
+
+pub fn path(kubenv_dir: &Path) -> PathBuf {
+    return kubenv_dir.join(".history");
+}
+
+pub fn load(kubenv_dir: &Path) -> History {
+    let content = match fs::read(path(kubenv_dir)) {
+        Ok(c) => c,
+        Err(_) => return History::new(),
+    };
+
+    if let Ok(history) = serde_json::from_slice(&content) {
+        return history;
+    }
+    // Older files store only the seconds
+    let legacy: BTreeMap<String, u64> = serde_json::from_slice(&content).unwrap_or_default();
+
+    return legacy
+        .into_iter()
+        .map(|(name, applied)| (name, Entry { seq: 0, applied }))
+        .collect();
+}
+
+// Names of the applied configs, the most recent first
+pub fn recent(kubenv_dir: &Path) -> Vec<String> {
+    let mut entries: Vec<(String, Entry)> = load(kubenv_dir).into_iter().collect();
+    entries.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+    return entries.into_iter().map(|(name, _)| name).collect();
+}
+
+fn save(kubenv_dir: &Path, history: &History) -> Result {
+    let content = match serde_json::to_vec(history) {
+        Ok(c) => c,
+        Err(msg) => return Err(Error::Metadata(msg.to_string())),
+    };
+
+    return write_atomic(&path(kubenv_dir), &content);
+}
+
+pub fn get(kubenv_dir: &Path, name: &str) -> Option<SystemTime> {
+    return load(kubenv_dir)
+        .get(name)
+        .map(|entry| UNIX_EPOCH + Duration::from_secs(entry.applied));
+}
+
+pub fn record(kubenv_dir: &Path, name: &str) -> Result {
+    let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs(),
+        Err(_) => 0,
+    };
+    let mut history = load(kubenv_dir);
+    let seq = history.values().map(|entry| entry.seq).max().unwrap_or(0) + 1;
+    history.insert(String::from(name), Entry { seq, applied: now });
+
+    return save(kubenv_dir, &history);
+}
+
+pub fn rename(kubenv_dir: &Path, old_name: &str, new_name: &str) -> Result {
+    let mut history = load(kubenv_dir);
+    let applied = match history.remove(old_name) {
+        Some(a) => a,
+        None => return Ok(()),
+    };
+    history.insert(String::from(new_name), applied);
+
+    return save(kubenv_dir, &history);
+}
+
+pub fn forget(kubenv_dir: &Path, name: &str) -> Result {
+    let mut history = load(kubenv_dir);
+    if history.remove(name).is_none() {
+        return Ok(());
+    }
+
+    return save(kubenv_dir, &history);
+}
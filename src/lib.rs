@@ -1,20 +1,47 @@
-use std::cmp::{Ordering, PartialOrd};
+#![allow(clippy::needless_return)]
+
+use std::cmp::{Ordering, Reverse};
+use std::env;
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::io::{BufReader, BufWriter, Read, Write};
-use std::path::PathBuf;
-use std::result;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{self, AtomicUsize};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 use hashbrown::HashMap;
-use home;
-use sha256;
+use similar::TextDiff;
+
+mod aliases;
+#[cfg(feature = "archive")]
+mod archive;
+mod crypt;
+mod error;
+mod event;
+mod gzip;
+mod hash;
+mod history;
+mod lock;
+mod meta;
+mod settings;
+mod yaml;
 
-pub type Result<T = ()> = result::Result<T, String>;
-const BUF_SIZE: usize = 1024;
+pub use crypt::{parse_key, Key};
+pub use error::{Error, Result};
+pub use event::{Event, EventSink, NoopSink, Operation};
+pub use hash::HashAlgorithm;
+
+pub const BUF_SIZE: usize = 64 * 1024;
+const IMPORT_EXTENSIONS: [&str; 3] = ["kubeconfig", "yaml", "yml"];
+const PRUNE_THREADS: usize = 8;
+#[cfg(feature = "watch")]
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
 
 fn get_default_kube_dir() -> Result<PathBuf> {
     let home_dir = match home::home_dir() {
         Some(path) => path.to_path_buf(),
-        None => return Err(String::from("Cannot get home directory")),
+        None => return Err(Error::HomeDirNotFound),
     };
 
     let kube_dir = home_dir.join(".kube");
@@ -27,35 +54,356 @@ fn get_default_kubenv_dir() -> Result<PathBuf> {
     return Ok(kubenv_dir);
 }
 
-fn get_file_hash(path: &PathBuf) -> Result<String> {
-    let hash = match sha256::try_digest(path.as_path()) {
-        Ok(bytes) => bytes,
-        Err(msg) => match path.to_str() {
-            Some(ps) => {
-                return Err(format!("Cannot get hash from file '{}': {}", ps, msg));
-            }
-            None => return Err(format!("Cannot get hash from file: {}", msg)),
+// The XDG spec ignores relative values of XDG_DATA_HOME
+#[cfg(not(windows))]
+fn get_xdg_kubenv_dir() -> Result<PathBuf> {
+    let data_dir = match env::var_os("XDG_DATA_HOME").map(PathBuf::from) {
+        Some(path) if path.is_absolute() => path,
+        _ => match home::home_dir() {
+            Some(path) => path.join(".local").join("share"),
+            None => return Err(Error::HomeDirNotFound),
+        },
+    };
+
+    return Ok(data_dir.join("kubenv"));
+}
+
+#[cfg(windows)]
+fn get_xdg_kubenv_dir() -> Result<PathBuf> {
+    return get_default_kubenv_dir();
+}
+
+// `~/.kube/kubenv` stays the default so existing installs are not relocated
+pub fn default_kubenv_dir(xdg: bool) -> Result<PathBuf> {
+    return match xdg {
+        true => get_xdg_kubenv_dir(),
+        false => get_default_kubenv_dir(),
+    };
+}
+
+fn get_file_hash(path: &Path, algorithm: HashAlgorithm) -> Result<String> {
+    return hash::file_digest(algorithm, path);
+}
+
+// Suffix after the config extension telling how the file is stored
+fn storage_suffix(path: &Path) -> &'static str {
+    if crypt::is_encrypted(path) {
+        return crypt::SUFFIX;
+    }
+    if gzip::is_compressed(path) {
+        return gzip::SUFFIX;
+    }
+
+    return "";
+}
+
+// Path of `target` with the storage suffix of `source`
+fn swapped_path(target: &Path, source: &Path) -> PathBuf {
+    let target = target.as_os_str().to_string_lossy();
+    let stem = &target[..target.len() - storage_suffix(Path::new(target.as_ref())).len()];
+
+    return PathBuf::from(format!("{}{}", stem, storage_suffix(source)));
+}
+
+fn open_config(path: &Path, key: Option<&Key>) -> Result<Box<dyn Read>> {
+    let file = match fs::File::open(path) {
+        Ok(f) => f,
+        Err(msg) => return Err(Error::io("Cannot open file", path, msg)),
+    };
+
+    return match (storage_suffix(path), key) {
+        (crypt::SUFFIX, Some(key)) => crypt::decrypter(file, key, path),
+        (crypt::SUFFIX, None) => Err(Error::KeyRequired(path.to_path_buf())),
+        (gzip::SUFFIX, _) => gzip::decoder(file),
+        _ => Ok(Box::new(file)),
+    };
+}
+
+fn read_config(path: &Path, key: Option<&Key>) -> Result<Vec<u8>> {
+    let mut content: Vec<u8> = Vec::new();
+    if let Err(msg) = open_config(path, key)?.read_to_end(&mut content) {
+        return Err(Error::io("Cannot read file", path, msg));
+    }
+
+    return Ok(content);
+}
+
+// Compressed and encrypted configs are hashed over their plain content, so
+// the hash does not depend on how a config is stored
+fn get_config_hash(path: &Path, key: Option<&Key>, algorithm: HashAlgorithm) -> Result<String> {
+    return match storage_suffix(path) {
+        "" => get_file_hash(path, algorithm),
+        _ => hash::digest(algorithm, &read_config(path, key)?),
+    };
+}
+
+fn write_config(path: &Path, content: &[u8], key: Option<&Key>) -> Result {
+    return match (storage_suffix(path), key) {
+        (crypt::SUFFIX, Some(key)) => write_atomic(path, &crypt::encrypt(content, key)?),
+        (crypt::SUFFIX, None) => Err(Error::KeyRequired(path.to_path_buf())),
+        (gzip::SUFFIX, _) => write_atomic(path, &gzip::compress(content)?),
+        _ => write_atomic(path, content),
+    };
+}
+
+fn scan_dir(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(_) => continue,
+        };
+        let path = entry.path();
+        // Symlinked directories are not followed to avoid cycles
+        let is_dir = match entry.file_type() {
+            Ok(file_type) => file_type.is_dir(),
+            Err(_) => continue,
+        };
+        if !is_dir {
+            files.push(path);
+            continue;
+        }
+        let hidden = match entry.file_name().to_str() {
+            Some(name) => name.starts_with('.'),
+            None => true,
+        };
+        if recursive && !hidden {
+            _ = scan_dir(&path, recursive, files);
+        }
+    }
+
+    return Ok(());
+}
+
+fn is_broken_link(path: &Path) -> bool {
+    return path.is_symlink() && !path.exists();
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut file_name = OsString::from(".");
+    if let Some(name) = path.file_name() {
+        file_name.push(name);
+    }
+    file_name.push(".tmp");
+
+    return path.with_file_name(file_name);
+}
+
+// Content is written to a temporary file in the same directory and renamed
+// into place, so readers never see a partially written file
+// Whether `path` resolves to the file at `config_file` itself. A symlink at
+// `config_file` is not followed, as it is replaced rather than written through
+fn is_same_file(path: &Path, config_file: &Path) -> bool {
+    let location = match (config_file.parent(), config_file.file_name()) {
+        (Some(parent), Some(file_name)) => match fs::canonicalize(parent) {
+            Ok(p) => p.join(file_name),
+            Err(_) => return path == config_file,
         },
+        _ => return path == config_file,
+    };
+
+    return match fs::canonicalize(path) {
+        Ok(p) => p == location,
+        Err(_) => path == config_file,
+    };
+}
+
+fn write_atomic(path: &Path, content: &[u8]) -> Result {
+    let tmp_path = temp_path(path);
+    let file = match fs::File::create(&tmp_path) {
+        Ok(f) => f,
+        Err(msg) => return Err(Error::io("Cannot open file", tmp_path, msg)),
+    };
+    let mut writer = BufWriter::with_capacity(BUF_SIZE, file);
+    let result = writer
+        .write_all(content)
+        .and_then(|_| writer.flush())
+        .and_then(|_| writer.get_ref().sync_all());
+    drop(writer);
+    if let Err(msg) = result {
+        _ = fs::remove_file(&tmp_path);
+        return Err(Error::io("Cannot write file", path, msg));
+    }
+    if let Err(msg) = fs::rename(&tmp_path, path) {
+        _ = fs::remove_file(&tmp_path);
+        return Err(Error::io("Cannot write file", path, msg));
+    }
+
+    return Ok(());
+}
+
+fn copy_atomic(source: &Path, path: &Path) -> Result {
+    let tmp_path = temp_path(path);
+    if let Err(msg) = fs::copy(source, &tmp_path) {
+        _ = fs::remove_file(&tmp_path);
+        return Err(Error::io("Cannot copy config to file", path, msg));
+    }
+    if let Err(msg) = fs::rename(&tmp_path, path) {
+        _ = fs::remove_file(&tmp_path);
+        return Err(Error::io("Cannot copy config to file", path, msg));
+    }
+
+    return Ok(());
+}
+
+pub fn copy<R: Read + ?Sized, W: Write + ?Sized>(reader: &mut R, writer: &mut W) -> Result<u64> {
+    return copy_with_buf_size(reader, writer, BUF_SIZE);
+}
+
+pub fn copy_with_buf_size<R: Read + ?Sized, W: Write + ?Sized>(
+    reader: &mut R,
+    writer: &mut W,
+    buf_size: usize,
+) -> Result<u64> {
+    let mut buffer = vec![0; buf_size.max(1)];
+    let mut total: u64 = 0;
+    loop {
+        let count = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(c) => c,
+            Err(msg) if msg.kind() == io::ErrorKind::Interrupted => continue,
+            Err(msg) => return Err(Error::stream("Cannot read", msg)),
+        };
+        if let Err(msg) = writer.write_all(&buffer[..count]) {
+            return Err(Error::stream("Cannot write", msg));
+        };
+        total += count as u64;
+    }
+
+    return Ok(total);
+}
+
+// Reports the cumulative number of bytes written after every write
+struct ProgressWriter<W: Write, F: FnMut(u64)> {
+    inner: W,
+    written: u64,
+    progress: F,
+}
+
+impl<W: Write, F: FnMut(u64)> ProgressWriter<W, F> {
+    fn new(inner: W, progress: F) -> Self {
+        return Self {
+            inner,
+            written: 0,
+            progress,
+        };
+    }
+
+    #[cfg(feature = "archive")]
+    fn into_inner(self) -> W {
+        return self.inner;
+    }
+}
+
+impl<W: Write, F: FnMut(u64)> Write for ProgressWriter<W, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.written += count as u64;
+        (self.progress)(self.written);
+
+        return Ok(count);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return self.inner.flush();
+    }
+}
+
+// Matches `*` against any run of characters and `?` against a single one
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    return match (pattern.first(), text.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            glob_match(&pattern[1..], text) || (!text.is_empty() && glob_match(pattern, &text[1..]))
+        }
+        (Some('?'), Some(_)) => glob_match(&pattern[1..], &text[1..]),
+        (Some(p), Some(t)) if p == t => glob_match(&pattern[1..], &text[1..]),
+        _ => false,
     };
+}
+
+#[cfg(unix)]
+fn symlink(original: &Path, link: &Path) -> io::Result<()> {
+    return std::os::unix::fs::symlink(original, link);
+}
+
+#[cfg(windows)]
+fn symlink(original: &Path, link: &Path) -> io::Result<()> {
+    return std::os::windows::fs::symlink_file(original, link);
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_original: &Path, _link: &Path) -> io::Result<()> {
+    return Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symlinks are not supported on this platform",
+    ));
+}
+
+fn validate_name(name: &str) -> Result {
+    if name.is_empty() {
+        return Err(Error::invalid_name(name, "name cannot be empty"));
+    }
+    if name.chars().any(std::path::is_separator) {
+        return Err(Error::invalid_name(
+            name,
+            "name cannot contain path separators",
+        ));
+    }
+    // Also covers `.` and `..`, and keeps names clear of kubenv's own dot files
+    if name.starts_with('.') {
+        return Err(Error::invalid_name(name, "name cannot start with a dot"));
+    }
+    if name.contains('\0') {
+        return Err(Error::invalid_name(name, "name cannot contain NUL bytes"));
+    }
+
+    return Ok(());
+}
 
-    return Ok(hash);
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Origin {
+    // Stored in the kubenv directory
+    Managed,
+    // The kube config itself, detected without being added to kubenv
+    Live,
 }
 
-#[derive(PartialEq)]
 pub struct KubeConfig {
     name: String,
     path: PathBuf,
     hash: String,
+    modified: SystemTime,
+    size: u64,
+    compressed: bool,
+    encrypted: bool,
+    origin: Origin,
+    metadata: meta::Metadata,
 }
 
 impl KubeConfig {
     pub fn new(path: PathBuf, hash: String, name: Option<String>) -> Self {
         let name = match name {
             Some(n) => n,
-            None => String::from(&hash[..8]),
+            // Hashes passed in by callers may be shorter than the usual prefix
+            None => String::from(hash.get(..hash.len().min(8)).unwrap_or(&hash)),
         };
 
-        return Self { name, path, hash };
+        let (modified, size) = match fs::metadata(&path) {
+            Ok(m) => (m.modified().unwrap_or(SystemTime::UNIX_EPOCH), m.len()),
+            Err(_) => (SystemTime::UNIX_EPOCH, 0),
+        };
+
+        return Self {
+            name,
+            compressed: gzip::is_compressed(&path),
+            encrypted: crypt::is_encrypted(&path),
+            path,
+            hash,
+            modified,
+            size,
+            origin: Origin::Managed,
+            metadata: meta::Metadata::default(),
+        };
     }
 
     pub fn name(&self) -> &str {
@@ -65,6 +413,57 @@ impl KubeConfig {
     pub fn hash(&self) -> &str {
         return &self.hash;
     }
+
+    // The first `len` characters of the hash, or all of it when shorter
+    pub fn fingerprint(&self, len: usize) -> &str {
+        let end = len.min(self.hash.len());
+
+        return self.hash.get(..end).unwrap_or(&self.hash);
+    }
+
+    pub fn path(&self) -> &Path {
+        return &self.path;
+    }
+
+    pub fn modified(&self) -> SystemTime {
+        return self.modified;
+    }
+
+    // Size of the stored file, which is the compressed size for compressed configs
+    pub fn size(&self) -> u64 {
+        return self.size;
+    }
+
+    pub fn compressed(&self) -> bool {
+        return self.compressed;
+    }
+
+    pub fn encrypted(&self) -> bool {
+        return self.encrypted;
+    }
+
+    pub fn origin(&self) -> Origin {
+        return self.origin;
+    }
+
+    pub fn is_managed(&self) -> bool {
+        return self.origin == Origin::Managed;
+    }
+
+    pub fn tags(&self) -> &[String] {
+        return &self.metadata.tags;
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        return self.metadata.description.as_deref();
+    }
+
+    // Clusters without a server are left out
+    pub fn server_urls(&self, key: Option<&Key>) -> Result<Vec<(String, String)>> {
+        let value = yaml::parse(&read_config(&self.path, key)?)?;
+
+        return Ok(yaml::server_urls(&value));
+    }
 }
 
 impl Clone for KubeConfig {
@@ -73,23 +472,153 @@ impl Clone for KubeConfig {
             name: self.name.clone(),
             hash: self.hash.clone(),
             path: self.path.clone(),
+            modified: self.modified,
+            size: self.size,
+            compressed: self.compressed,
+            encrypted: self.encrypted,
+            origin: self.origin,
+            metadata: self.metadata.clone(),
         };
     }
 }
 
+impl PartialEq for KubeConfig {
+    fn eq(&self, other: &Self) -> bool {
+        return self.name == other.name && self.hash == other.hash;
+    }
+}
+
+impl Eq for KubeConfig {}
+
 impl PartialOrd for KubeConfig {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        return Some(self.name().cmp(other.name()));
+        return Some(self.cmp(other));
+    }
+}
+
+impl Ord for KubeConfig {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return self
+            .name
+            .cmp(&other.name)
+            .then_with(|| self.hash.cmp(&other.hash));
+    }
+}
+
+pub struct Contexts {
+    pub names: Vec<String>,
+    pub current: Option<String>,
+}
+
+pub struct ConfigInfo {
+    pub clusters: Vec<String>,
+    pub contexts: Contexts,
+    pub users: Vec<String>,
+    pub servers: Vec<(String, String)>,
+}
+
+pub struct SyncReport {
+    pub loaded: usize,
+    pub skipped: usize,
+    pub broken_links: Vec<PathBuf>,
+    pub current: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Pass,
+    Warn,
+    Fail,
+}
+
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub check: String,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, check: &str, message: String, hint: Option<&str>) -> Self {
+        return Self {
+            severity,
+            check: String::from(check),
+            message,
+            hint: hint.map(String::from),
+        };
     }
 }
 
+// Names and hashes sort ascending, sizes and times largest or most recent
+// first. Configs that were never applied come last
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Hash,
+    Size,
+    Modified,
+    LastApplied,
+}
+
+pub struct Stats {
+    pub configs: usize,
+    // Stored configs with the same content as the applied one
+    pub current: usize,
+    // Config count per tag, sorted by tag
+    pub tags: Vec<(String, usize)>,
+    pub bytes: u64,
+}
+
+#[cfg(feature = "archive")]
+pub struct ArchiveReport {
+    pub extracted: usize,
+    pub skipped: Vec<PathBuf>,
+}
+
+pub struct ExportReport {
+    pub exported: usize,
+    pub errors: Vec<Error>,
+}
+
+pub struct ImportReport {
+    pub imported: Vec<KubeConfig>,
+    pub skipped: Vec<(PathBuf, Error)>,
+}
+
+pub struct ApplyPlan {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub backup: Option<PathBuf>,
+    pub changed: bool,
+}
+
 pub struct KubEnv {
     kube_dir: PathBuf,
+    config_file: Option<PathBuf>,
+    // A KUBECONFIG with several paths, which only fails the commands writing
+    // to the config file
+    kubeconfig_paths: Option<String>,
+    config_filename: String,
     kubenv_dir: PathBuf,
+    extensions: Vec<String>,
+    recursive: bool,
+    ignore_case: bool,
+    compress: bool,
+    encrypt: bool,
+    key: Option<Key>,
+    name_prefix_len: usize,
+    lock_timeout: Option<Duration>,
+    http_timeout: Duration,
+    http_retries: u32,
+    hash_algorithm: HashAlgorithm,
+    buf_size: usize,
+    event_sink: Box<dyn EventSink>,
     current_config: Option<KubeConfig>,
     configs: Vec<KubeConfig>,
     configs_by_name: HashMap<String, KubeConfig>,
-    configs_by_hash: HashMap<String, KubeConfig>,
+    aliases: aliases::Aliases,
+    configs_by_hash: HashMap<String, Vec<KubeConfig>>,
 }
 
 impl KubEnv {
@@ -104,233 +633,3237 @@ impl KubEnv {
         };
         let kubenv = Self {
             kube_dir,
+            config_file: None,
+            kubeconfig_paths: None,
+            config_filename: String::from("config"),
             kubenv_dir,
+            extensions: vec![String::from("kubeconfig")],
+            recursive: false,
+            ignore_case: false,
+            compress: false,
+            encrypt: false,
+            key: None,
+            name_prefix_len: 8,
+            lock_timeout: Some(Duration::from_secs(10)),
+            http_timeout: Duration::from_secs(10),
+            http_retries: 0,
+            hash_algorithm: HashAlgorithm::Sha256,
+            buf_size: BUF_SIZE,
+            event_sink: Box::new(NoopSink),
             current_config: None,
             configs: vec![],
             configs_by_name: HashMap::new(),
+            aliases: aliases::Aliases::new(),
             configs_by_hash: HashMap::new(),
         };
         return Ok(kubenv);
     }
 
-    pub fn current_config(&self) -> Option<&KubeConfig> {
-        return self.current_config.as_ref();
+    pub fn with_kubeconfig_env(mut self) -> Self {
+        if let Some(value) = env::var_os("KUBECONFIG") {
+            self.set_kubeconfig(&value);
+        }
+
+        return self;
     }
 
-    pub fn configs(&self) -> Vec<&KubeConfig> {
-        return self.configs.iter().collect();
+    pub fn with_config_filename(mut self, filename: String) -> Self {
+        self.config_filename = filename;
+        return self;
     }
 
-    pub fn get_content(&self, name: &str) -> Result<BufReader<fs::File>> {
-        let kubeconfig = match self.get_config_by_name(name) {
-            Some(kc) => kc,
-            None => return Err(format!("Cannot find config with name '{}'", name)),
-        };
+    pub fn with_recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
 
-        return match fs::File::open(&kubeconfig.path) {
-            Ok(f) => Ok(BufReader::with_capacity(BUF_SIZE, f)),
-            Err(msg) => match kubeconfig.path.to_str() {
-                Some(path) => Err(format!("Cannot open file '{}': {}", path, msg)),
-                None => Err(format!("Cannot open file: {}", msg)),
-            },
-        };
+        return self;
     }
 
-    pub fn set_content<R: Read>(&self, name: Option<String>, reader: &mut BufReader<R>) -> Result {
-        let mut content: Vec<u8> = Vec::new();
-        if let Err(msg) = reader.read_to_end(&mut content) {
-            return Err(format!("Cannot read content from file: {}", msg));
-        };
+    // For case-insensitive filesystems, where 'Prod' and 'prod' are one file.
+    // Names are still stored and shown as written
+    pub fn with_ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        return self;
+    }
 
-        let hash = sha256::digest(&content as &[u8]);
-        if let Some(kc) = self.get_config_by_hash(&hash) {
-            return Err(format!("Config already exists with name: '{}'", &kc.name));
-        }
-        let name = match name {
-            Some(n) => {
-                if let Some(kc) = self.get_config_by_name(&n) {
-                    return Err(format!("Config with name '{}' already exists", kc.name));
-                }
-                n
-            }
-            None => hash.clone(),
-        };
+    pub fn with_compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        return self;
+    }
 
-        let mut kubeconfig_filename = name.clone();
-        kubeconfig_filename.push_str(".kubeconfig");
-        let kubeconfig_path = self.kubenv_dir.join(kubeconfig_filename);
-        let mut writer = match fs::File::create(&kubeconfig_path) {
-            Ok(f) => BufWriter::with_capacity(BUF_SIZE, f),
-            Err(msg) => match kubeconfig_path.to_str() {
-                Some(p) => return Err(format!("Cannot open file '{}': {}", p, msg)),
-                None => return Err(format!("Cannot open file: {}", msg)),
-            },
-        };
-        if let Err(msg) = writer.write_all(&content) {
-            match kubeconfig_path.to_str() {
-                Some(p) => return Err(format!("Cannot write file '{}': {}", p, msg)),
-                None => return Err(format!("Cannot write file: {}", msg)),
-            }
-        }
+    // New configs are encrypted to `key`, which is also needed to read them
+    pub fn with_encrypt(mut self, encrypt: bool) -> Self {
+        self.encrypt = encrypt;
+        return self;
+    }
 
-        return Ok(());
+    pub fn with_key(mut self, key: Option<Key>) -> Self {
+        self.key = key;
+        return self;
     }
 
-    pub fn apply(&self, name: &str) -> Result {
-        let kubeconfig = match self.get_config_by_name(name) {
-            Some(kc) => kc,
-            None => return Err(format!("Cannot find config with name '{}'", name)),
-        };
+    pub fn with_name_prefix_len(mut self, len: usize) -> Self {
+        self.name_prefix_len = len.max(1);
 
-        let config_file = self.kube_dir.join("config");
-        if let Ok(hash) = get_file_hash(&config_file) {
-            if hash == kubeconfig.hash {
-                return Err(format!("Config '{}' already applied", name));
-            }
-        }
-        if let Err(msg) = fs::copy(&kubeconfig.path, config_file) {
-            return Err(format!(
-                "Cannot copy config '{}' to config file: {}",
-                kubeconfig.name, msg,
-            ));
-        }
+        return self;
+    }
 
-        return Ok(());
+    pub fn with_lock_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.lock_timeout = timeout;
+        return self;
     }
 
-    pub fn remove(&self, name: &str) -> Result {
-        let kubeconfig = match self.get_config_by_name(name) {
-            Some(kc) => kc,
-            None => return Err(format!("Cannot find config with name '{}'", name)),
-        };
+    pub fn with_buf_size(mut self, buf_size: usize) -> Self {
+        self.buf_size = buf_size.max(1);
+        return self;
+    }
 
-        if let Err(msg) = fs::remove_file(&kubeconfig.path) {
-            return Err(format!(
-                "Cannot remove config with name '{}': {}",
-                kubeconfig.name, msg,
-            ));
+    pub fn with_event_sink<S: EventSink + 'static>(mut self, sink: S) -> Self {
+        self.event_sink = Box::new(sink);
+        return self;
+    }
+
+    pub fn with_extensions(mut self, extensions: Vec<String>) -> Self {
+        let extensions: Vec<String> = extensions
+            .iter()
+            .map(|ext| String::from(ext.trim_start_matches('.')))
+            .filter(|ext| !ext.is_empty())
+            .collect();
+        if !extensions.is_empty() {
+            self.extensions = extensions;
         }
 
-        return Ok(());
+        return self;
     }
 
-    pub fn sync(&mut self) -> Result {
-        if !self.kubenv_dir.is_dir() {
-            if let Err(msg) = fs::create_dir_all(self.kubenv_dir.as_path()) {
-                match self.kubenv_dir.to_str() {
-                    Some(path) => {
-                        return Err(format!(
-                            "Cannot create kubenv directory '{}': {}",
-                            path, msg,
-                        ))
-                    }
-                    None => return Err(format!("Cannot create kubenv directory: {}", msg)),
-                }
-            }
-        };
+    pub fn buf_size(&self) -> usize {
+        return self.buf_size;
+    }
 
-        self.update_configs()?;
-        _ = self.update_current_config();
+    // Changing it changes every hash, and with them the hash-prefix names
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = algorithm;
+        return self;
+    }
 
-        return Ok(());
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        return self.hash_algorithm;
     }
 
-    fn get_config_by_name(&self, name: &str) -> Option<&KubeConfig> {
-        return self.configs_by_name.get(name);
+    pub fn with_http_timeout(mut self, timeout: Duration) -> Self {
+        self.http_timeout = timeout;
+        return self;
     }
 
-    fn get_config_by_hash(&self, hash: &str) -> Option<&KubeConfig> {
-        return self.configs_by_hash.get(hash);
+    // Retries apply to timeouts, connection errors and 5xx responses only
+    pub fn with_http_retries(mut self, retries: u32) -> Self {
+        self.http_retries = retries;
+        return self;
     }
 
-    fn update_configs(&mut self) -> Result {
-        let config_files = match fs::read_dir(&self.kubenv_dir) {
-            Ok(value) => value,
-            Err(msg) => match self.kubenv_dir.to_str() {
-                Some(path) => {
-                    return Err(format!(
-                        "Cannot read files from directory '{}': {}",
-                        path, msg,
-                    ))
-                }
-                None => return Err(format!("Cannot read files from kubenv directory: {}", msg)),
-            },
-        };
+    pub fn http_timeout(&self) -> Duration {
+        return self.http_timeout;
+    }
 
-        self.configs.clear();
-        self.configs_by_name.clear();
-        self.configs_by_hash.clear();
-        for config_file in config_files {
-            let config_file = match config_file {
-                Ok(cf) => cf,
-                Err(_) => continue,
-            };
-            let path = config_file.path();
-            let file_name = match config_file.file_name().to_str() {
-                Some(ps) => String::from(ps),
-                None => continue,
-            };
-            if path.is_file() && file_name.ends_with(".kubeconfig") {
-                let name = match file_name.strip_suffix(".kubeconfig") {
-                    Some(s) => String::from(s),
-                    None => file_name.clone(),
-                };
-                let hash = match get_file_hash(&path) {
-                    Ok(h) => h,
-                    Err(_) => continue,
-                };
-                if let Err(_) = self.add(KubeConfig::new(path, hash, Some(name))) {
-                    continue;
-                };
-            };
-        }
+    pub fn http_retries(&self) -> u32 {
+        return self.http_retries;
+    }
 
-        return Ok(());
+    pub fn kubenv_dir(&self) -> &Path {
+        return &self.kubenv_dir;
     }
 
-    fn update_current_config(&mut self) -> Result {
-        let current_config_file = self.kube_dir.join("config");
-        let hash = get_file_hash(&current_config_file)?;
+    pub fn kube_dir(&self) -> &Path {
+        return &self.kube_dir;
+    }
 
-        if current_config_file.is_file() {
-            let kubeconfig = KubeConfig::new(current_config_file, hash, None);
-            self.current_config = Some(kubeconfig.clone());
-            _ = self.add(kubeconfig.clone());
-        }
+    pub fn config_file(&self) -> PathBuf {
+        return match &self.config_file {
+            Some(path) => path.clone(),
+            None => self.kube_dir.join(&self.config_filename),
+        };
+    }
 
-        return Ok(());
+    pub fn current_config(&self) -> Option<&KubeConfig> {
+        return self.current_config.as_ref();
     }
 
-    fn add(&mut self, kubeconfig: KubeConfig) -> Result {
-        if let Some(kc) = self.get_config_by_name(&kubeconfig.name) {
-            return Err(format!("Config with name '{}' already exists", kc.name));
+    pub fn configs(&self) -> Vec<&KubeConfig> {
+        return self.configs.iter().collect();
+    }
+
+    // In the same order as `configs`, without collecting them
+    pub fn config_count(&self) -> usize {
+        return self.configs.len();
+    }
+
+    pub fn config_at(&self, index: usize) -> Option<&KubeConfig> {
+        return self.configs.get(index);
+    }
+
+    pub fn configs_sorted_by(&self, key: SortKey) -> Vec<&KubeConfig> {
+        let mut configs = self.configs();
+        self.sort_configs(&mut configs, key);
+
+        return configs;
+    }
+
+    // The sort is stable, so configs with equal keys stay in name order
+    pub fn sort_configs(&self, configs: &mut [&KubeConfig], key: SortKey) {
+        match key {
+            SortKey::Name => configs.sort_by(|a, b| a.name.cmp(&b.name)),
+            SortKey::Hash => configs.sort_by(|a, b| a.hash.cmp(&b.hash)),
+            SortKey::Size => configs.sort_by_key(|kc| Reverse(kc.size)),
+            SortKey::Modified => configs.sort_by_key(|kc| Reverse(kc.modified)),
+            SortKey::LastApplied => {
+                let history = history::load(&self.kubenv_dir);
+                configs.sort_by_key(|kc| Reverse(history.get(&kc.name).copied()));
+            }
+        }
+    }
+
+    pub fn configs_by_tag(&self, tag: &str) -> Vec<&KubeConfig> {
+        return self
+            .configs
+            .iter()
+            .filter(|kc| kc.metadata.tags.iter().any(|t| t == tag))
+            .collect();
+    }
+
+    pub fn configs_by_tag_ignore_case(&self, tag: &str) -> Vec<&KubeConfig> {
+        return self
+            .configs
+            .iter()
+            .filter(|kc| kc.metadata.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+            .collect();
+    }
+
+    // Patterns without `*` or `?` match any name containing them
+    pub fn find(&self, pattern: &str) -> Vec<&KubeConfig> {
+        if !pattern.contains(['*', '?']) {
+            return self
+                .configs
+                .iter()
+                .filter(|kc| kc.name.contains(pattern))
+                .collect();
+        }
+
+        let pattern: Vec<char> = pattern.chars().collect();
+        return self
+            .configs
+            .iter()
+            .filter(|kc| glob_match(&pattern, &kc.name.chars().collect::<Vec<char>>()))
+            .collect();
+    }
+
+    pub fn config_names(&self) -> impl Iterator<Item = &str> {
+        return self.configs.iter().map(|kc| kc.name.as_str());
+    }
+
+    pub fn is_current(&self, name: &str) -> bool {
+        return match (&self.current_config, self.get_config_by_name(name)) {
+            (Some(current), Some(kc)) => current.hash == kc.hash,
+            _ => false,
+        };
+    }
+
+    pub fn is_applied(&self, name: &str) -> Result<bool> {
+        let kubeconfig = match self.get_config_by_name(name) {
+            Some(kc) => kc,
+            None => return Err(Error::ConfigNotFound(String::from(name))),
+        };
+        return match &self.current_config {
+            Some(current) => Ok(current.hash == kubeconfig.hash),
+            None => Ok(false),
+        };
+    }
+
+    // A default naming a config that no longer exists is ignored
+    pub fn default_config(&self) -> Option<&KubeConfig> {
+        return match settings::load(&self.kubenv_dir).default {
+            Some(name) => self.get_config_by_name(&name),
+            None => None,
+        };
+    }
+
+    pub fn duplicates(&self) -> Vec<(String, String)> {
+        let mut duplicates: Vec<(String, String)> = vec![];
+        for kubeconfig in &self.configs {
+            if let Some(first) = self.get_config_by_hash(&kubeconfig.hash) {
+                if first.name != kubeconfig.name {
+                    duplicates.push((first.name.clone(), kubeconfig.name.clone()));
+                }
+            }
+        }
+
+        return duplicates;
+    }
+
+    // Real names take precedence over aliases
+    pub fn get_config_by_name(&self, name: &str) -> Option<&KubeConfig> {
+        return match self.configs_by_name.get(&self.name_key(name)) {
+            Some(kc) => Some(kc),
+            None => match self.aliases.get(name) {
+                Some(target) => self.configs_by_name.get(&self.name_key(target)),
+                None => None,
+            },
+        };
+    }
+
+    pub fn aliases(&self) -> Vec<(&str, &str)> {
+        return self
+            .aliases
+            .iter()
+            .map(|(alias, name)| (alias.as_str(), name.as_str()))
+            .collect();
+    }
+
+    // Aliases shadowed by a config with the same name
+    pub fn alias_clashes(&self) -> Vec<&str> {
+        return self
+            .aliases
+            .keys()
+            .filter(|alias| self.configs_by_name.contains_key(&self.name_key(alias)))
+            .map(|alias| alias.as_str())
+            .collect();
+    }
+
+    pub fn set_alias(&self, alias: &str, name: &str) -> Result {
+        let _lock = self.lock()?;
+        validate_name(alias)?;
+        if self.configs_by_name.contains_key(&self.name_key(alias)) {
+            return Err(Error::DuplicateName(String::from(alias)));
+        }
+        let target = match self.get_config_by_name(name) {
+            Some(kc) => kc.name.clone(),
+            None => return Err(Error::ConfigNotFound(String::from(name))),
+        };
+
+        let mut aliases = aliases::load(&self.kubenv_dir);
+        aliases.insert(String::from(alias), target);
+        return aliases::save(&self.kubenv_dir, &aliases);
+    }
+
+    pub fn remove_alias(&self, alias: &str) -> Result {
+        let _lock = self.lock()?;
+        let mut aliases = aliases::load(&self.kubenv_dir);
+        if aliases.remove(alias).is_none() {
+            return Err(Error::AliasNotFound(String::from(alias)));
+        }
+
+        return aliases::save(&self.kubenv_dir, &aliases);
+    }
+
+    // Only configs stored in the kubenv directory are counted
+    pub fn stats(&self) -> Stats {
+        let managed: Vec<&KubeConfig> = self.configs.iter().filter(|kc| kc.is_managed()).collect();
+        let current = match &self.current_config {
+            Some(current) => managed.iter().filter(|kc| kc.hash == current.hash).count(),
+            None => 0,
+        };
+        let mut tags: HashMap<&str, usize> = HashMap::new();
+        for kubeconfig in &managed {
+            for tag in &kubeconfig.metadata.tags {
+                *tags.entry(tag.as_str()).or_insert(0) += 1;
+            }
+        }
+        let mut tags: Vec<(String, usize)> = tags
+            .into_iter()
+            .map(|(tag, count)| (String::from(tag), count))
+            .collect();
+        tags.sort();
+
+        return Stats {
+            configs: managed.len(),
+            current,
+            tags,
+            bytes: managed.iter().map(|kc| kc.size).sum(),
+        };
+    }
+
+    pub fn get_config_by_hash(&self, hash: &str) -> Option<&KubeConfig> {
+        return self.get_configs_by_hash(hash).first().copied();
+    }
+
+    pub fn get_configs_by_hash(&self, hash: &str) -> Vec<&KubeConfig> {
+        return match self.configs_by_hash.get(hash) {
+            Some(kubeconfigs) => kubeconfigs.iter().collect(),
+            None => vec![],
+        };
+    }
+
+    pub fn get_content(&self, name: &str) -> Result<BufReader<Box<dyn Read>>> {
+        let kubeconfig = match self.get_config_by_name(name) {
+            Some(kc) => kc,
+            None => return Err(Error::ConfigNotFound(String::from(name))),
+        };
+
+        return Ok(BufReader::with_capacity(
+            self.buf_size,
+            open_config(&kubeconfig.path, self.key.as_ref())?,
+        ));
+    }
+
+    // The whole content in memory, `get_content` streams it instead
+    pub fn get_content_bytes(&self, name: &str) -> Result<Vec<u8>> {
+        return match self.get_config_by_name(name) {
+            Some(kc) => read_config(&kc.path, self.key.as_ref()),
+            None => Err(Error::ConfigNotFound(String::from(name))),
+        };
+    }
+
+    pub fn export<W: Write>(&self, name: &str, writer: &mut W) -> Result {
+        let mut reader = self.get_content(name)?;
+
+        copy_with_buf_size(&mut reader, writer, self.buf_size)?;
+
+        return Ok(());
+    }
+
+    pub fn export_with_progress<W: Write, F: FnMut(u64)>(
+        &self,
+        name: &str,
+        writer: &mut W,
+        progress: F,
+    ) -> Result {
+        let mut reader = self.get_content(name)?;
+
+        copy_with_buf_size(
+            &mut reader,
+            &mut ProgressWriter::new(writer, progress),
+            self.buf_size,
+        )?;
+
+        return Ok(());
+    }
+
+    // Streams into a temporary file next to `path` that is renamed over it
+    // once complete, so a failed export leaves `path` as it was
+    pub fn export_file<F: FnMut(u64)>(&self, name: &str, path: &Path, progress: F) -> Result {
+        let mut reader = self.get_content(name)?;
+        let tmp_path = temp_path(path);
+        let file = match fs::File::create(&tmp_path) {
+            Ok(f) => f,
+            Err(msg) => return Err(Error::io("Cannot open file", tmp_path, msg)),
+        };
+
+        let mut writer = ProgressWriter::new(file, progress);
+        let result = copy_with_buf_size(&mut reader, &mut writer, self.buf_size).and_then(|_| {
+            return match writer.inner.sync_all() {
+                Ok(()) => Ok(()),
+                Err(msg) => Err(Error::io("Cannot write file", path, msg)),
+            };
+        });
+        drop(writer);
+        if let Err(err) = result {
+            _ = fs::remove_file(&tmp_path);
+            return Err(err);
+        }
+        if let Err(msg) = fs::rename(&tmp_path, path) {
+            _ = fs::remove_file(&tmp_path);
+            return Err(Error::io("Cannot write file", path, msg));
+        }
+
+        return Ok(());
+    }
+
+    pub fn export_all(&self, dest_dir: &Path) -> Result<ExportReport> {
+        if let Err(msg) = fs::create_dir_all(dest_dir) {
+            return Err(Error::io("Cannot create directory", dest_dir, msg));
+        }
+
+        let mut report = ExportReport {
+            exported: 0,
+            errors: vec![],
+        };
+        // The auto-detected kube config is not stored, so it is not exported
+        for kubeconfig in self.configs.iter().filter(|kc| kc.is_managed()) {
+            let path = dest_dir.join(format!("{}.kubeconfig", kubeconfig.name));
+            let result = match kubeconfig.compressed || kubeconfig.encrypted {
+                true => read_config(&kubeconfig.path, self.key.as_ref())
+                    .and_then(|c| write_atomic(&path, &c)),
+                false => copy_atomic(&kubeconfig.path, &path),
+            };
+            match result {
+                Ok(()) => report.exported += 1,
+                Err(err) => report.errors.push(err),
+            }
+        }
+
+        return Ok(report);
+    }
+
+    #[cfg(feature = "archive")]
+    pub fn archive(&self, dest: &Path) -> Result<usize> {
+        return self.archive_with_progress(dest, |_| {});
+    }
+
+    #[cfg(feature = "archive")]
+    pub fn archive_with_progress<F: FnMut(u64)>(&self, dest: &Path, progress: F) -> Result<usize> {
+        let mut files: Vec<PathBuf> = vec![];
+        let mut count = 0;
+        for kubeconfig in self.configs.iter().filter(|kc| kc.is_managed()) {
+            files.push(kubeconfig.path.clone());
+            let meta_path = meta::path(&kubeconfig.path);
+            if meta_path.is_file() {
+                files.push(meta_path);
+            }
+            count += 1;
+        }
+        archive::write(dest, &self.kubenv_dir, &files, progress)?;
+
+        return Ok(count);
+    }
+
+    #[cfg(feature = "archive")]
+    pub fn unarchive(&self, src: &Path) -> Result<ArchiveReport> {
+        let _lock = self.lock()?;
+        let mut report = ArchiveReport {
+            extracted: 0,
+            skipped: vec![],
+        };
+        let mut extracted: Vec<PathBuf> = vec![];
+        archive::read(src, &self.kubenv_dir, |path| {
+            let dest = self.kubenv_dir.join(path);
+            if dest.exists() {
+                report.skipped.push(path.to_path_buf());
+                return false;
+            }
+            // Sidecars follow their config and are only taken along with it
+            if let Some(config_path) = dest.to_str().and_then(|p| p.strip_suffix(".meta")) {
+                return extracted.iter().any(|p| p.as_os_str() == config_path);
+            }
+
+            let taken = match self.config_name(&dest) {
+                Some(name) => self.get_config_by_name(&name).is_some(),
+                None => true,
+            };
+            if taken {
+                report.skipped.push(path.to_path_buf());
+                return false;
+            }
+            extracted.push(dest);
+            report.extracted += 1;
+            return true;
+        })?;
+
+        return Ok(report);
+    }
+
+    pub fn get_pretty_content(&self, name: &str) -> Result<Vec<u8>> {
+        let value = yaml::parse(&self.get_content_bytes(name)?)?;
+
+        return yaml::to_bytes(&yaml::canonicalize(value));
+    }
+
+    pub fn set_content<R: Read>(
+        &self,
+        name: Option<String>,
+        reader: &mut BufReader<R>,
+        validate: bool,
+        overwrite: bool,
+    ) -> Result<KubeConfig> {
+        let _lock = self.lock()?;
+        let mut content: Vec<u8> = Vec::new();
+        if let Err(msg) = reader.read_to_end(&mut content) {
+            return Err(Error::stream("Cannot read content from file", msg));
+        };
+        // Empty content is never a usable config, even without validation
+        if content.is_empty() {
+            return Err(Error::Validation(String::from("content is empty")));
+        }
+        if validate {
+            yaml::validate(&content)?;
+        }
+
+        let hash = hash::digest(self.hash_algorithm, &content)?;
+        let (name, kubeconfig_path) = self.content_target(name, &hash, overwrite)?;
+        write_config(&kubeconfig_path, &content, self.key.as_ref())?;
+        self.remember_hash(&kubeconfig_path, &hash)?;
+        self.emit(Event::new(Operation::Add, &name, &hash));
+
+        return Ok(KubeConfig::new(kubeconfig_path, hash, Some(name)));
+    }
+
+    // Stores only `context` of the input, with its cluster and user
+    pub fn import_context<R: Read>(
+        &self,
+        name: Option<String>,
+        reader: &mut BufReader<R>,
+        context: &str,
+        validate: bool,
+        overwrite: bool,
+    ) -> Result<KubeConfig> {
+        let mut content: Vec<u8> = Vec::new();
+        if let Err(msg) = reader.read_to_end(&mut content) {
+            return Err(Error::stream("Cannot read content from file", msg));
+        };
+        let extracted = yaml::extract_context(&yaml::parse(&content)?, context)?;
+        let content = yaml::to_bytes(&extracted)?;
+
+        return self.set_content(
+            name,
+            &mut BufReader::new(content.as_slice()),
+            validate,
+            overwrite,
+        );
+    }
+
+    pub fn import_from_path(
+        &self,
+        name: Option<String>,
+        source: &Path,
+        validate: bool,
+        overwrite: bool,
+    ) -> Result<KubeConfig> {
+        let _lock = self.lock()?;
+        if fs::metadata(source).is_ok_and(|metadata| metadata.len() == 0) {
+            return Err(Error::Validation(String::from("content is empty")));
+        }
+        if validate {
+            match fs::read(source) {
+                Ok(content) => yaml::validate(&content)?,
+                Err(msg) => return Err(Error::io("Cannot read file", source, msg)),
+            }
+        }
+
+        let hash = get_file_hash(source, self.hash_algorithm)?;
+        let (name, kubeconfig_path) = self.content_target(name, &hash, overwrite)?;
+        match storage_suffix(&kubeconfig_path) {
+            "" => copy_atomic(source, &kubeconfig_path)?,
+            _ => write_config(
+                &kubeconfig_path,
+                &read_config(source, None)?,
+                self.key.as_ref(),
+            )?,
+        }
+        self.remember_hash(&kubeconfig_path, &hash)?;
+        self.emit(Event::new(Operation::Add, &name, &hash));
+
+        return Ok(KubeConfig::new(kubeconfig_path, hash, Some(name)));
+    }
+
+    #[cfg(feature = "url")]
+    pub fn import_from_url(
+        &self,
+        name: Option<String>,
+        url: &str,
+        headers: &[String],
+        validate: bool,
+        overwrite: bool,
+    ) -> Result<KubeConfig> {
+        let agent = ureq::AgentBuilder::new().timeout(self.http_timeout).build();
+        let mut request = agent.get(url);
+        for header in headers {
+            request = match header.split_once(':') {
+                Some((key, value)) => request.set(key.trim(), value.trim()),
+                None => {
+                    return Err(Error::Http {
+                        url: String::from(url),
+                        message: format!("invalid header '{}', expected 'Name: value'", header),
+                    })
+                }
+            };
+        }
+        // Backoff doubles from half a second, capped at 30 seconds
+        let mut attempt = 0;
+        let response = loop {
+            let (message, transient) = match request.clone().call() {
+                Ok(r) => break r,
+                Err(ureq::Error::Status(code, response)) => (
+                    format!("server responded with {} {}", code, response.status_text()),
+                    code >= 500,
+                ),
+                Err(err) => (err.to_string(), true),
+            };
+            if !transient || attempt >= self.http_retries {
+                let message = match attempt {
+                    0 => message,
+                    _ => format!("{} after {} attempts", message, attempt + 1),
+                };
+                return Err(Error::Http {
+                    url: String::from(url),
+                    message,
+                });
+            }
+            thread::sleep(Duration::from_millis((500 << attempt.min(6)).min(30_000)));
+            attempt += 1;
+        };
+
+        let mut reader = BufReader::with_capacity(self.buf_size, response.into_reader());
+        return self.set_content(name, &mut reader, validate, overwrite);
+    }
+
+    pub fn import_dir(&mut self, source_dir: &Path, validate: bool) -> Result<ImportReport> {
+        let mut files: Vec<PathBuf> = vec![];
+        if let Err(msg) = scan_dir(source_dir, false, &mut files) {
+            return Err(Error::io(
+                "Cannot read files from directory",
+                source_dir,
+                msg,
+            ));
+        }
+        files.sort();
+
+        let mut report = ImportReport {
+            imported: vec![],
+            skipped: vec![],
+        };
+        for path in files {
+            let file_name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(n) => n,
+                None => continue,
+            };
+            let name = IMPORT_EXTENSIONS
+                .iter()
+                .copied()
+                .chain(self.extensions.iter().map(|ext| ext.as_str()))
+                .find_map(|ext| file_name.strip_suffix(&format!(".{}", ext)))
+                .filter(|name| !name.is_empty());
+            let name = match name {
+                Some(n) => String::from(n),
+                None => continue,
+            };
+            // Index every import so later files in the batch see it as taken
+            let result = self
+                .import_from_path(Some(name), &path, validate, false)
+                .and_then(|kubeconfig| {
+                    self.add(kubeconfig.clone())?;
+                    return Ok(kubeconfig);
+                });
+            match result {
+                Ok(kubeconfig) => report.imported.push(kubeconfig),
+                Err(err) => report.skipped.push((path, err)),
+            }
+        }
+
+        return Ok(report);
+    }
+
+    // Returns the name of the managed config that was applied before
+    pub fn apply(&self, name: &str, force: bool, backup: bool) -> Result<Option<String>> {
+        let _lock = self.lock()?;
+        let kubeconfig = match self.get_config_by_name(name) {
+            Some(kc) => kc,
+            None => return Err(Error::ConfigNotFound(String::from(name))),
+        };
+
+        let config_file = self.apply_target()?;
+        self.check_source(kubeconfig, &config_file)?;
+        if !force {
+            self.check_apply(kubeconfig, &config_file)?;
+        }
+        let previous = self.applied_name(&config_file);
+        if backup {
+            self.backup()?;
+        }
+        // Copying onto a symlink left by `apply_symlink` would overwrite the
+        // stored config it points to, so the link itself is removed first
+        if let Ok(metadata) = fs::symlink_metadata(&config_file) {
+            if metadata.file_type().is_symlink() {
+                if let Err(msg) = fs::remove_file(&config_file) {
+                    return Err(Error::io("Cannot remove file", config_file, msg));
+                }
+            }
+        }
+        let result = match kubeconfig.compressed || kubeconfig.encrypted {
+            true => fs::write(
+                &config_file,
+                read_config(&kubeconfig.path, self.key.as_ref())?,
+            ),
+            false => fs::copy(&kubeconfig.path, &config_file).map(|_| ()),
+        };
+        if let Err(msg) = result {
+            return Err(Error::io("Cannot copy config to file", config_file, msg));
+        }
+        // History is informational, failing to record it does not fail the apply
+        _ = history::record(&self.kubenv_dir, &kubeconfig.name);
+        self.emit(Event::new(
+            Operation::Apply,
+            &kubeconfig.name,
+            &kubeconfig.hash,
+        ));
+
+        return Ok(previous);
+    }
+
+    pub fn apply_dry_run(&self, name: &str, force: bool, backup: bool) -> Result<ApplyPlan> {
+        let kubeconfig = match self.get_config_by_name(name) {
+            Some(kc) => kc,
+            None => return Err(Error::ConfigNotFound(String::from(name))),
+        };
+
+        let config_file = self.apply_target()?;
+        self.check_source(kubeconfig, &config_file)?;
+        let changed = match get_file_hash(&config_file, self.hash_algorithm) {
+            Ok(hash) => hash != kubeconfig.hash,
+            Err(_) => true,
+        };
+        if !force {
+            match self.check_apply(kubeconfig, &config_file) {
+                Ok(()) | Err(Error::AlreadyApplied(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let backup = match backup && changed && config_file.is_file() {
+            true => Some(self.backup_file()),
+            false => None,
+        };
+
+        return Ok(ApplyPlan {
+            source: kubeconfig.path.clone(),
+            destination: config_file,
+            backup,
+            changed,
+        });
+    }
+
+    pub fn apply_symlink(&self, name: &str, force: bool, backup: bool) -> Result<Option<String>> {
+        let _lock = self.lock()?;
+        let kubeconfig = match self.get_config_by_name(name) {
+            Some(kc) => kc,
+            None => return Err(Error::ConfigNotFound(String::from(name))),
+        };
+
+        if kubeconfig.compressed {
+            return Err(Error::Compressed {
+                name: kubeconfig.name.clone(),
+                action: String::from("linked"),
+            });
+        }
+        if kubeconfig.encrypted {
+            return Err(Error::Encrypted {
+                name: kubeconfig.name.clone(),
+                action: String::from("linked"),
+            });
+        }
+
+        let config_file = self.apply_target()?;
+        self.check_source(kubeconfig, &config_file)?;
+        if !force {
+            self.check_apply(kubeconfig, &config_file)?;
+        }
+        let target = match fs::canonicalize(&kubeconfig.path) {
+            Ok(path) => path,
+            Err(msg) => return Err(Error::io("Cannot resolve path", &kubeconfig.path, msg)),
+        };
+
+        let previous = self.applied_name(&config_file);
+        if backup {
+            self.backup()?;
+        }
+        self.link_config(&target, &config_file)?;
+        _ = history::record(&self.kubenv_dir, &kubeconfig.name);
+        self.emit(Event::new(
+            Operation::Apply,
+            &kubeconfig.name,
+            &kubeconfig.hash,
+        ));
+
+        return Ok(previous);
+    }
+
+    pub fn touch(&self, name: &str) -> Result {
+        let _lock = self.lock()?;
+        let kubeconfig = match self.get_config_by_name(name) {
+            Some(kc) => kc,
+            None => return Err(Error::ConfigNotFound(String::from(name))),
+        };
+
+        return history::record(&self.kubenv_dir, &kubeconfig.name);
+    }
+
+    pub fn last_applied(&self, name: &str) -> Option<SystemTime> {
+        return match self.get_config_by_name(name) {
+            Some(kc) => history::get(&self.kubenv_dir, &kc.name),
+            None => history::get(&self.kubenv_dir, name),
+        };
+    }
+
+    // The second most recent entry of the history, the one applied before the last
+    pub fn previous_config(&self) -> Result<String> {
+        return match history::recent(&self.kubenv_dir).into_iter().nth(1) {
+            Some(name) => Ok(name),
+            None => Err(Error::NoPreviousConfig),
+        };
+    }
+
+    pub fn restore_backup(&self) -> Result {
+        let _lock = self.lock()?;
+        let config_file = self.apply_target()?;
+        let backup_file = self.backup_file();
+        if !backup_file.is_file() {
+            return Err(Error::BackupNotFound(backup_file));
+        }
+
+        if fs::symlink_metadata(&config_file).is_err() {
+            if let Err(msg) = fs::rename(&backup_file, &config_file) {
+                return Err(Error::io("Cannot restore backup", backup_file, msg));
+            }
+            return Ok(());
+        }
+
+        // The current config and the backup are swapped, so restoring twice
+        // brings back the config that was replaced
+        let tmp_file = temp_path(&config_file);
+        if let Err(msg) = fs::rename(&config_file, &tmp_file) {
+            return Err(Error::io("Cannot restore backup", config_file, msg));
+        }
+        if let Err(msg) = fs::rename(&backup_file, &config_file) {
+            _ = fs::rename(&tmp_file, &config_file);
+            return Err(Error::io("Cannot restore backup", backup_file, msg));
+        }
+        if let Err(msg) = fs::rename(&tmp_file, &backup_file) {
+            return Err(Error::io("Cannot write backup", backup_file, msg));
+        }
+
+        return Ok(());
+    }
+
+    pub fn remove(&self, name: &str) -> Result {
+        let _lock = self.lock()?;
+        let kubeconfig = match self.get_config_by_name(name) {
+            Some(kc) => kc,
+            None => return Err(Error::ConfigNotFound(String::from(name))),
+        };
+        // Removing the live config would delete the kube config itself, so
+        // nothing outside the kubenv directory is ever unlinked
+        if !kubeconfig.is_managed() || !kubeconfig.path.starts_with(&self.kubenv_dir) {
+            return Err(Error::NotManaged(kubeconfig.name.clone()));
+        }
+
+        if let Err(msg) = fs::remove_file(&kubeconfig.path) {
+            return Err(Error::io("Cannot remove file", &kubeconfig.path, msg));
+        }
+        _ = fs::remove_file(meta::path(&kubeconfig.path));
+        self.forget_default(&kubeconfig.name)?;
+        _ = history::forget(&self.kubenv_dir, &kubeconfig.name);
+        let mut aliases = aliases::load(&self.kubenv_dir);
+        let count = aliases.len();
+        aliases.retain(|_, target| *target != kubeconfig.name);
+        if aliases.len() != count {
+            aliases::save(&self.kubenv_dir, &aliases)?;
+        }
+        self.emit(Event::new(
+            Operation::Remove,
+            &kubeconfig.name,
+            &kubeconfig.hash,
+        ));
+
+        return Ok(());
+    }
+
+    pub fn remove_by_hash(&self, hash: &str) -> Result {
+        let name = self.find_by_hash_prefix(hash)?.name.clone();
+
+        return self.remove(&name);
+    }
+
+    pub fn clone_config(&self, source_name: &str, new_name: &str) -> Result<KubeConfig> {
+        let _lock = self.lock()?;
+        let source = match self.get_config_by_name(source_name) {
+            Some(kc) => kc,
+            None => return Err(Error::ConfigNotFound(String::from(source_name))),
+        };
+        validate_name(new_name)?;
+        if let Some(kc) = self.configs_by_name.get(&self.name_key(new_name)) {
+            return Err(Error::DuplicateName(kc.name.clone()));
+        }
+
+        // The clone shares its hash with the source, so no duplicate hash check here
+        let suffix = storage_suffix(&source.path);
+        let kubeconfig_path = self
+            .kubenv_dir
+            .join(format!("{}.{}{}", new_name, self.extensions[0], suffix));
+        if kubeconfig_path.exists() {
+            return Err(Error::FileExists(kubeconfig_path));
+        }
+        copy_atomic(&source.path, &kubeconfig_path)?;
+        if source.metadata != meta::Metadata::default() {
+            meta::save(&kubeconfig_path, &source.metadata)?;
+        }
+
+        let mut kubeconfig = KubeConfig::new(
+            kubeconfig_path,
+            source.hash.clone(),
+            Some(String::from(new_name)),
+        );
+        kubeconfig.metadata = source.metadata.clone();
+
+        return Ok(kubeconfig);
+    }
+
+    pub fn rename(&mut self, old_name: &str, new_name: &str) -> Result {
+        let _lock = self.lock()?;
+        let kubeconfig = match self.get_config_by_name(old_name) {
+            Some(kc) => kc.clone(),
+            None => return Err(Error::ConfigNotFound(String::from(old_name))),
+        };
+        let old_name = kubeconfig.name.as_str();
+        validate_name(new_name)?;
+        if old_name == new_name {
+            return Err(Error::invalid_name(
+                new_name,
+                "config already has this name",
+            ));
+        }
+        // A case-only rename finds the config itself when names ignore case
+        if let Some(kc) = self.configs_by_name.get(&self.name_key(new_name)) {
+            if kc.name != old_name {
+                return Err(Error::DuplicateName(kc.name.clone()));
+            }
+        }
+        if !kubeconfig.is_managed() {
+            return Err(Error::NotManaged(kubeconfig.name.clone()));
+        }
+
+        // Keep the extension the config was stored with
+        let suffix = match kubeconfig.path.file_name().and_then(|name| name.to_str()) {
+            Some(file_name) => self.config_suffix(file_name).unwrap_or_default(),
+            None => String::new(),
+        };
+        // A config found in a subdirectory stays in it, so the new name has to
+        // start with the same directory prefix
+        let dir = match kubeconfig.path.parent() {
+            Some(dir) => dir.to_path_buf(),
+            None => self.kubenv_dir.clone(),
+        };
+        let mut prefix = String::new();
+        if let Ok(relative) = dir.strip_prefix(&self.kubenv_dir) {
+            for component in relative.components() {
+                prefix.push_str(&component.as_os_str().to_string_lossy());
+                prefix.push('_');
+            }
+        }
+        let stem = match new_name.strip_prefix(prefix.as_str()) {
+            Some(stem) => stem,
+            None => {
+                return Err(Error::invalid_name(
+                    new_name,
+                    &format!("config in a subdirectory must keep the '{}' prefix", prefix),
+                ))
+            }
+        };
+        validate_name(stem)?;
+        let kubeconfig_path = dir.join(format!("{}{}", stem, suffix));
+        if kubeconfig_path.exists() {
+            return Err(Error::FileExists(kubeconfig_path));
+        }
+        // A config applied with `apply_symlink` is linked to its old path
+        let config_file = self.config_file();
+        let linked = match (
+            fs::read_link(&config_file),
+            fs::canonicalize(&kubeconfig.path),
+        ) {
+            (Ok(target), Ok(path)) => target == path,
+            _ => false,
+        };
+        if let Err(msg) = fs::rename(&kubeconfig.path, &kubeconfig_path) {
+            return Err(Error::io("Cannot rename file", &kubeconfig.path, msg));
+        }
+        let meta_path = meta::path(&kubeconfig.path);
+        if meta_path.is_file() {
+            if let Err(msg) = fs::rename(&meta_path, meta::path(&kubeconfig_path)) {
+                return Err(Error::io("Cannot rename file", meta_path, msg));
+            }
+        }
+        if linked {
+            match fs::canonicalize(&kubeconfig_path) {
+                Ok(target) => self.link_config(&target, &config_file)?,
+                Err(msg) => return Err(Error::io("Cannot resolve path", kubeconfig_path, msg)),
+            }
+        }
+
+        self.unindex(old_name);
+        let mut renamed = KubeConfig::new(
+            kubeconfig_path,
+            kubeconfig.hash.clone(),
+            Some(String::from(new_name)),
+        );
+        renamed.metadata = kubeconfig.metadata.clone();
+        self.add(renamed.clone())?;
+        _ = history::rename(&self.kubenv_dir, old_name, new_name);
+        let mut settings = settings::load(&self.kubenv_dir);
+        if settings.default.as_deref() == Some(old_name) {
+            settings.default = Some(String::from(new_name));
+            settings::save(&self.kubenv_dir, &settings)?;
+        }
+        let mut aliases = aliases::load(&self.kubenv_dir);
+        let mut renamed_aliases = false;
+        for target in aliases.values_mut().filter(|target| *target == old_name) {
+            *target = String::from(new_name);
+            renamed_aliases = true;
+        }
+        if renamed_aliases {
+            aliases::save(&self.kubenv_dir, &aliases)?;
+            self.aliases = aliases;
+        }
+        if let Some(current) = &self.current_config {
+            if current.name == kubeconfig.name {
+                self.current_config = Some(renamed);
+            }
+        }
+        let mut event = Event::new(Operation::Rename, new_name, &kubeconfig.hash);
+        event.from = Some(kubeconfig.name.clone());
+        self.emit(event);
+
+        return Ok(());
+    }
+
+    // Exchanges the content of two configs, tags and descriptions move with
+    // the content. Defaults, aliases and history stay with the names
+    pub fn swap(&mut self, first: &str, second: &str) -> Result {
+        let _lock = self.lock()?;
+        let mut pair: Vec<KubeConfig> = Vec::with_capacity(2);
+        for name in [first, second] {
+            let kubeconfig = match self.get_config_by_name(name) {
+                Some(kc) => kc.clone(),
+                None => return Err(Error::ConfigNotFound(String::from(name))),
+            };
+            if !kubeconfig.is_managed() {
+                return Err(Error::NotManaged(kubeconfig.name));
+            }
+            pair.push(kubeconfig);
+        }
+        let (first, second) = (&pair[0], &pair[1]);
+        if first.name == second.name {
+            return Err(Error::invalid_name(
+                &second.name,
+                "cannot swap a config with itself",
+            ));
+        }
+
+        // Each name keeps its path, only the storage suffix follows the content
+        let first_path = swapped_path(&second.path, &first.path);
+        let second_path = swapped_path(&first.path, &second.path);
+        let config_file = self.config_file();
+        let linked = fs::read_link(&config_file).ok();
+        let links_to = |path: &Path| match (&linked, fs::canonicalize(path)) {
+            (Some(target), Ok(path)) => *target == path,
+            _ => false,
+        };
+        let (first_linked, second_linked) = (links_to(&first.path), links_to(&second.path));
+
+        // The temporary file has no config extension, so after a crash it is
+        // never loaded as a config
+        let tmp_path = self.kubenv_dir.join(".swap.tmp");
+        for (path, freed) in [
+            (&tmp_path, None),
+            (&first_path, Some(&second.path)),
+            (&second_path, Some(&first.path)),
+        ] {
+            if Some(path) != freed && path.exists() {
+                return Err(Error::FileExists(path.clone()));
+            }
+        }
+        if let Err(msg) = fs::rename(&first.path, &tmp_path) {
+            return Err(Error::io("Cannot rename file", &first.path, msg));
+        }
+        if let Err(msg) = fs::rename(&second.path, &second_path) {
+            _ = fs::rename(&tmp_path, &first.path);
+            return Err(Error::io("Cannot rename file", &second.path, msg));
+        }
+        if let Err(msg) = fs::rename(&tmp_path, &first_path) {
+            _ = fs::rename(&second_path, &second.path);
+            _ = fs::rename(&tmp_path, &first.path);
+            return Err(Error::io("Cannot rename file", &first.path, msg));
+        }
+
+        for kubeconfig in [first, second] {
+            _ = fs::remove_file(meta::path(&kubeconfig.path));
+        }
+        for (kubeconfig, path) in [(first, &first_path), (second, &second_path)] {
+            if kubeconfig.metadata != meta::Metadata::default() {
+                meta::save(path, &kubeconfig.metadata)?;
+            }
+        }
+        for (linked, path) in [(first_linked, &first_path), (second_linked, &second_path)] {
+            if linked {
+                match fs::canonicalize(path) {
+                    Ok(target) => self.link_config(&target, &config_file)?,
+                    Err(msg) => return Err(Error::io("Cannot resolve path", path, msg)),
+                }
+            }
+        }
+
+        self.unindex(&first.name);
+        self.unindex(&second.name);
+        for (kubeconfig, name, path) in [
+            (first, &second.name, &first_path),
+            (second, &first.name, &second_path),
+        ] {
+            let mut swapped =
+                KubeConfig::new(path.clone(), kubeconfig.hash.clone(), Some(name.clone()));
+            swapped.metadata = kubeconfig.metadata.clone();
+            self.add(swapped)?;
+            let mut event = Event::new(Operation::Rename, name, &kubeconfig.hash);
+            event.from = Some(kubeconfig.name.clone());
+            self.emit(event);
+        }
+        _ = self.update_current_config();
+
+        return Ok(());
+    }
+
+    pub fn set_default(&self, name: &str) -> Result {
+        let _lock = self.lock()?;
+        let kubeconfig = match self.get_config_by_name(name) {
+            Some(kc) => kc,
+            None => return Err(Error::ConfigNotFound(String::from(name))),
+        };
+
+        let mut settings = settings::load(&self.kubenv_dir);
+        settings.default = Some(kubeconfig.name.clone());
+        return settings::save(&self.kubenv_dir, &settings);
+    }
+
+    pub fn unset_default(&self) -> Result {
+        let _lock = self.lock()?;
+        let mut settings = settings::load(&self.kubenv_dir);
+        if settings.default.is_none() {
+            return Ok(());
+        }
+
+        settings.default = None;
+        return settings::save(&self.kubenv_dir, &settings);
+    }
+
+    pub fn set_tags(&self, name: &str, tags: Vec<String>) -> Result {
+        let _lock = self.lock()?;
+        let kubeconfig = match self.get_config_by_name(name) {
+            Some(kc) => kc,
+            None => return Err(Error::ConfigNotFound(String::from(name))),
+        };
+        if !kubeconfig.is_managed() {
+            return Err(Error::NotManaged(kubeconfig.name.clone()));
+        }
+
+        let mut metadata = kubeconfig.metadata.clone();
+        metadata.tags = tags;
+        if metadata == meta::Metadata::default() {
+            let meta_path = meta::path(&kubeconfig.path);
+            if meta_path.is_file() {
+                if let Err(msg) = fs::remove_file(&meta_path) {
+                    return Err(Error::io("Cannot remove file", meta_path, msg));
+                }
+            }
+            return Ok(());
+        }
+
+        return meta::save(&kubeconfig.path, &metadata);
+    }
+
+    pub fn verify(&self, name: &str) -> Result<bool> {
+        let kubeconfig = match self.get_config_by_name(name) {
+            Some(kc) => kc,
+            None => return Err(Error::ConfigNotFound(String::from(name))),
+        };
+
+        return Ok(
+            get_config_hash(&kubeconfig.path, self.key.as_ref(), self.hash_algorithm)?
+                == kubeconfig.hash,
+        );
+    }
+
+    // A config is unreachable when none of its servers pass `check`. Configs
+    // without servers and the unmanaged kube config are never reported
+    pub fn unreachable<F: Fn(&str) -> bool + Sync>(&self, check: F) -> Vec<&KubeConfig> {
+        let mut candidates: Vec<(&KubeConfig, Vec<String>)> = vec![];
+        for kubeconfig in self.configs.iter().filter(|kc| kc.is_managed()) {
+            let urls: Vec<String> = match kubeconfig.server_urls(self.key.as_ref()) {
+                Ok(servers) => servers.into_iter().map(|(_, url)| url).collect(),
+                Err(_) => continue,
+            };
+            if !urls.is_empty() {
+                candidates.push((kubeconfig, urls));
+            }
+        }
+        let mut urls: Vec<&str> = candidates
+            .iter()
+            .flat_map(|(_, urls)| urls.iter().map(|url| url.as_str()))
+            .collect();
+        urls.sort_unstable();
+        urls.dedup();
+
+        // Workers pull URLs from a shared index, so at most PRUNE_THREADS probes run at once
+        let next = AtomicUsize::new(0);
+        let mut reachable: HashMap<&str, bool> = HashMap::with_capacity(urls.len());
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..PRUNE_THREADS.min(urls.len()))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut results: Vec<(&str, bool)> = vec![];
+                        loop {
+                            let index = next.fetch_add(1, atomic::Ordering::Relaxed);
+                            match urls.get(index) {
+                                Some(url) => results.push((*url, check(url))),
+                                None => return results,
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for worker in workers {
+                if let Ok(results) = worker.join() {
+                    reachable.extend(results);
+                }
+            }
+        });
+
+        return candidates
+            .iter()
+            .filter(|(_, urls)| {
+                !urls
+                    .iter()
+                    .any(|url| reachable.get(url.as_str()).copied().unwrap_or(true))
+            })
+            .map(|(kubeconfig, _)| *kubeconfig)
+            .collect();
+    }
+
+    pub fn prune<F: Fn(&str) -> bool + Sync>(&self, check: F) -> Result<Vec<String>> {
+        let names: Vec<String> = self
+            .unreachable(check)
+            .into_iter()
+            .map(|kc| kc.name.clone())
+            .collect();
+        for name in &names {
+            self.remove(name)?;
+        }
+
+        return Ok(names);
+    }
+
+    pub fn check_hash(&self, name: &str, expected: &str) -> Result {
+        let kubeconfig = match self.get_config_by_name(name) {
+            Some(kc) => kc,
+            None => return Err(Error::ConfigNotFound(String::from(name))),
+        };
+
+        let actual = get_config_hash(&kubeconfig.path, self.key.as_ref(), self.hash_algorithm)?;
+        if !actual.eq_ignore_ascii_case(expected.trim()) {
+            return Err(Error::HashMismatch {
+                name: String::from(name),
+                expected: String::from(expected),
+                actual,
+            });
+        }
+
+        return Ok(());
+    }
+
+    pub fn validate(&self, name: &str) -> Result {
+        return yaml::validate(&self.get_content_bytes(name)?);
+    }
+
+    pub fn diff(&self, a: &str, b: &str) -> Result<String> {
+        let mut contents: Vec<String> = Vec::with_capacity(2);
+        for name in [a, b] {
+            let content = self.get_content_bytes(name)?;
+            contents.push(String::from_utf8_lossy(&content).into_owned());
+        }
+
+        let diff = TextDiff::from_lines(&contents[0], &contents[1])
+            .unified_diff()
+            .header(a, b)
+            .to_string();
+
+        return Ok(diff);
+    }
+
+    pub fn current_context_name(&self) -> Result<Option<String>> {
+        let value = match self.read_config_file()? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        return Ok(yaml::current_context(&value));
+    }
+
+    // None when the context has no namespace, which kubectl treats as "default"
+    pub fn current_namespace(&self) -> Result<Option<String>> {
+        let value = match self.read_config_file()? {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        return Ok(match yaml::current_context(&value) {
+            Some(context) => yaml::context_namespace(&value, &context),
+            None => None,
+        });
+    }
+
+    pub fn set_current_context(&mut self, context: &str) -> Result {
+        let _lock = self.lock()?;
+        let config_file = self.apply_target()?;
+        let mut value = match self.read_config_file()? {
+            Some(v) => v,
+            None => {
+                return Err(Error::io(
+                    "Cannot read file",
+                    config_file,
+                    io::Error::from(io::ErrorKind::NotFound),
+                ))
+            }
+        };
+        yaml::set_current_context(&mut value, context)?;
+
+        // Write through a link left by `apply_symlink` instead of replacing it
+        let path = match fs::canonicalize(&config_file) {
+            Ok(p) => p,
+            Err(msg) => return Err(Error::io("Cannot resolve path", config_file, msg)),
+        };
+        write_atomic(&path, &yaml::to_bytes(&value)?)?;
+        drop(_lock);
+
+        // The hash of the current config changed, and with a link so did the
+        // hash of the stored config
+        self.reload()?;
+
+        return Ok(());
+    }
+
+    pub fn list_contexts(&self, name: &str) -> Result<Contexts> {
+        let value = yaml::parse(&self.get_content_bytes(name)?)?;
+
+        return Ok(Contexts {
+            names: yaml::names(&value, "contexts"),
+            current: yaml::current_context(&value),
+        });
+    }
+
+    pub fn info(&self, name: &str) -> Result<ConfigInfo> {
+        let value = yaml::parse(&self.get_content_bytes(name)?)?;
+
+        return Ok(ConfigInfo {
+            clusters: yaml::names(&value, "clusters"),
+            contexts: Contexts {
+                names: yaml::names(&value, "contexts"),
+                current: yaml::current_context(&value),
+            },
+            users: yaml::names(&value, "users"),
+            servers: yaml::server_urls(&value),
+        });
+    }
+
+    pub fn merge(&self, names: &[String], new_name: &str) -> Result<KubeConfig> {
+        let _lock = self.lock()?;
+        let mut values = Vec::with_capacity(names.len());
+        for name in names {
+            values.push((name.as_str(), yaml::parse(&self.get_content_bytes(name)?)?));
+        }
+        let content = yaml::to_bytes(&yaml::merge(&values)?)?;
+
+        let hash = hash::digest(self.hash_algorithm, &content)?;
+        let name = self.new_config_name(Some(String::from(new_name)), &hash)?;
+        let kubeconfig_path = self.config_path(&name);
+        write_config(&kubeconfig_path, &content, self.key.as_ref())?;
+        self.remember_hash(&kubeconfig_path, &hash)?;
+        self.emit(Event::new(Operation::Add, &name, &hash));
+
+        return Ok(KubeConfig::new(kubeconfig_path, hash, Some(name)));
+    }
+
+    pub fn merge_into_current(&self, name: &str) -> Result {
+        let _lock = self.lock()?;
+        let config_file = self.apply_target()?;
+        let current_name = config_file.display().to_string();
+        let mut values = Vec::with_capacity(2);
+        if let Some(value) = self.read_config_file()? {
+            values.push((current_name.as_str(), value));
+        }
+        values.push((name, yaml::parse(&self.get_content_bytes(name)?)?));
+        let content = yaml::to_bytes(&yaml::merge(&values)?)?;
+
+        self.backup()?;
+        // Replacing a link left by `apply_symlink` keeps the stored config untouched
+        return write_atomic(&config_file, &content);
+    }
+
+    pub fn diagnose(&self) -> Vec<Diagnostic> {
+        let mut diagnostics: Vec<Diagnostic> = vec![];
+        let kubenv_dir = self.kubenv_dir.display().to_string();
+        // Writability is checked by creating a file, permission bits alone miss ACLs and read-only mounts
+        let probe = self.kubenv_dir.join(".doctor");
+        let kubenv_dir_check = match fs::metadata(&self.kubenv_dir) {
+            Ok(m) if !m.is_dir() => Diagnostic::new(
+                Severity::Fail,
+                "kubenv dir",
+                format!("'{}' is not a directory", kubenv_dir),
+                Some("Remove the file or pass another directory with --dir"),
+            ),
+            Ok(_) => match fs::File::create(&probe) {
+                Ok(_) => {
+                    _ = fs::remove_file(&probe);
+                    Diagnostic::new(Severity::Pass, "kubenv dir", kubenv_dir, None)
+                }
+                Err(msg) => Diagnostic::new(
+                    Severity::Fail,
+                    "kubenv dir",
+                    format!("'{}' is not writable: {}", kubenv_dir, msg),
+                    Some("Fix the permissions of the directory"),
+                ),
+            },
+            Err(msg) => Diagnostic::new(
+                Severity::Fail,
+                "kubenv dir",
+                format!("'{}' is not accessible: {}", kubenv_dir, msg),
+                Some("Create the directory or pass another one with --dir"),
+            ),
+        };
+        diagnostics.push(kubenv_dir_check);
+
+        let kube_dir = self.kube_dir.display().to_string();
+        diagnostics.push(match self.kube_dir.is_dir() {
+            true => Diagnostic::new(Severity::Pass, "kube dir", kube_dir, None),
+            false => Diagnostic::new(
+                Severity::Warn,
+                "kube dir",
+                format!("'{}' does not exist, configs cannot be applied", kube_dir),
+                Some("Create it with 'mkdir ~/.kube' or pass another one with --kube-dir"),
+            ),
+        });
+
+        let config_file = self.config_file().display().to_string();
+        diagnostics.push(match &self.current_config {
+            Some(kc) if kc.is_managed() => Diagnostic::new(
+                Severity::Pass,
+                "current config",
+                format!("'{}' is config '{}'", config_file, kc.name),
+                None,
+            ),
+            Some(_) => Diagnostic::new(
+                Severity::Warn,
+                "current config",
+                format!("'{}' is not managed by kubenv", config_file),
+                Some(&format!(
+                    "Add it with 'kubenv add --file {}' before applying another config",
+                    config_file,
+                )),
+            ),
+            None => Diagnostic::new(
+                Severity::Warn,
+                "current config",
+                format!("'{}' does not exist", config_file),
+                Some("Apply a config with 'kubenv apply'"),
+            ),
+        });
+
+        // Files that failed to load were skipped silently during sync
+        let mut files: Vec<PathBuf> = vec![];
+        _ = scan_dir(&self.kubenv_dir, self.recursive, &mut files);
+        let mut names: HashMap<String, PathBuf> = HashMap::new();
+        let mut unreadable = 0;
+        let mut collisions = 0;
+        let backup_file = self.backup_file();
+        for path in files {
+            if path == backup_file {
+                continue;
+            }
+            let name = match self.config_name(&path) {
+                Some(n) => n,
+                None => continue,
+            };
+            if is_broken_link(&path) {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Warn,
+                    "config files",
+                    format!("'{}' is a broken symlink and ignored", path.display()),
+                    Some("Remove the link or restore its target"),
+                ));
+                continue;
+            }
+            if !path.is_file() {
+                continue;
+            }
+            if fs::metadata(&path).is_ok_and(|metadata| metadata.len() == 0) {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Warn,
+                    "config files",
+                    format!("'{}' is empty and ignored", path.display()),
+                    Some("Remove the file"),
+                ));
+                continue;
+            }
+            match get_config_hash(&path, self.key.as_ref(), self.hash_algorithm) {
+                Ok(_) => {}
+                // Encrypted configs stay usable without the key through their recorded hash
+                Err(err @ Error::KeyRequired(_)) => diagnostics.push(Diagnostic::new(
+                    Severity::Warn,
+                    "config files",
+                    err.to_string(),
+                    Some("Set KUBENV_KEY or KUBENV_KEY_FILE to read encrypted configs"),
+                )),
+                Err(err) => {
+                    diagnostics.push(Diagnostic::new(
+                        Severity::Fail,
+                        "config files",
+                        err.to_string(),
+                        Some("Fix the file permissions or remove the file"),
+                    ));
+                    unreadable += 1;
+                    continue;
+                }
+            }
+            if let Some(other) = names.get(&name) {
+                diagnostics.push(Diagnostic::new(
+                    Severity::Warn,
+                    "config names",
+                    format!(
+                        "'{}' and '{}' both map to name '{}', one is ignored",
+                        other.display(),
+                        path.display(),
+                        name,
+                    ),
+                    Some("Rename one of the files"),
+                ));
+                collisions += 1;
+                continue;
+            }
+            names.insert(name, path);
+        }
+        if unreadable == 0 {
+            diagnostics.push(Diagnostic::new(
+                Severity::Pass,
+                "config files",
+                format!("{} files readable", names.len()),
+                None,
+            ));
+        }
+        if collisions == 0 {
+            diagnostics.push(Diagnostic::new(
+                Severity::Pass,
+                "config names",
+                String::from("no name collisions"),
+                None,
+            ));
+        }
+
+        let duplicates = self.duplicates();
+        diagnostics.push(match duplicates.is_empty() {
+            true => Diagnostic::new(
+                Severity::Pass,
+                "duplicates",
+                String::from("no configs share content"),
+                None,
+            ),
+            false => Diagnostic::new(
+                Severity::Warn,
+                "duplicates",
+                format!(
+                    "{} configs have the same content as another",
+                    duplicates.len()
+                ),
+                Some("List them with 'kubenv duplicates'"),
+            ),
+        });
+        for alias in self.alias_clashes() {
+            diagnostics.push(Diagnostic::new(
+                Severity::Warn,
+                "aliases",
+                format!(
+                    "alias '{}' is shadowed by the config with the same name",
+                    alias
+                ),
+                Some("Remove it with 'kubenv alias --remove'"),
+            ));
+        }
+
+        return diagnostics;
+    }
+
+    // Old and new names of the managed configs not yet named after a prefix of
+    // their hash. New names never reuse a current name
+    pub fn normalize_plan(&self) -> Vec<(String, String)> {
+        let mut taken: Vec<String> = self.configs.iter().map(|kc| kc.name.clone()).collect();
+        let mut plan: Vec<(String, String)> = vec![];
+        for kubeconfig in self.configs.iter().filter(|kc| kc.is_managed()) {
+            let normalized = kubeconfig.name.len()
+                >= self.name_prefix_len.min(kubeconfig.hash.len())
+                && kubeconfig.hash.starts_with(&kubeconfig.name);
+            if normalized {
+                continue;
+            }
+            let hash = &kubeconfig.hash;
+            let mut len = self.name_prefix_len.min(hash.len());
+            while len < hash.len() && taken.iter().any(|name| *name == hash[..len]) {
+                len += 1;
+            }
+            let name = String::from(&hash[..len]);
+            taken.push(name.clone());
+            plan.push((kubeconfig.name.clone(), name));
+        }
+
+        return plan;
+    }
+
+    pub fn normalize_names(&mut self) -> Result<Vec<(String, String)>> {
+        let plan = self.normalize_plan();
+        for (old_name, new_name) in &plan {
+            self.rename(old_name, new_name)?;
+        }
+
+        return Ok(plan);
+    }
+
+    pub fn sync(&mut self) -> Result<SyncReport> {
+        if !self.kubenv_dir.is_dir() {
+            if let Err(msg) = fs::create_dir_all(self.kubenv_dir.as_path()) {
+                return Err(Error::io(
+                    "Cannot create kubenv directory",
+                    &self.kubenv_dir,
+                    msg,
+                ));
+            }
+        };
+
+        return self.reload();
+    }
+
+    // Like `sync`, but never creates the kubenv directory
+    pub fn reload(&mut self) -> Result<SyncReport> {
+        if !self.kubenv_dir.is_dir() {
+            return Err(Error::KubenvDirNotFound(self.kubenv_dir.clone()));
+        }
+
+        let mut report = self.update_configs()?;
+        _ = self.update_current_config();
+        report.current = self.current_config.as_ref().map(|kc| kc.name.clone());
+
+        return Ok(report);
+    }
+
+    // Blocks forever, re-syncing after every burst of changes to the kubenv
+    // directory or the kube config
+    #[cfg(feature = "watch")]
+    pub fn watch<F: FnMut(&KubEnv)>(&mut self, mut on_change: F) -> Result {
+        use notify::{EventKind, RecursiveMode, Watcher};
+        use std::sync::mpsc;
+
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = match notify::recommended_watcher(sender) {
+            Ok(w) => w,
+            Err(msg) => return Err(Error::Watch(msg.to_string())),
+        };
+        let mode = match self.recursive {
+            true => RecursiveMode::Recursive,
+            false => RecursiveMode::NonRecursive,
+        };
+        if let Err(msg) = watcher.watch(&self.kubenv_dir, mode) {
+            return Err(Error::Watch(msg.to_string()));
+        }
+        // The kube config is replaced on apply, so its directory is watched instead
+        let config_file = self.config_file();
+        if let Some(dir) = config_file.parent().filter(|dir| dir.is_dir()) {
+            if let Err(msg) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                return Err(Error::Watch(msg.to_string()));
+            }
+        }
+        let kubenv_dir = self.kubenv_dir.clone();
+        let ignored = [kubenv_dir.join(".lock"), history::path(&kubenv_dir)];
+        let relevant = |event: &notify::Event| {
+            if let EventKind::Access(_) = event.kind {
+                return false;
+            }
+            return event.paths.iter().any(|path| {
+                (path.starts_with(&kubenv_dir) && !ignored.contains(path)) || *path == config_file
+            });
+        };
+
+        loop {
+            let event = match receiver.recv() {
+                Ok(Ok(e)) => e,
+                Ok(Err(msg)) => return Err(Error::Watch(msg.to_string())),
+                Err(_) => return Ok(()),
+            };
+            if !relevant(&event) {
+                continue;
+            }
+            // Wait for the burst to settle so it triggers a single sync
+            while receiver.recv_timeout(WATCH_DEBOUNCE).is_ok() {}
+            self.reload()?;
+            on_change(self);
+        }
+    }
+
+    pub fn refresh(&mut self, name: &str) -> Result<KubeConfig> {
+        let kubeconfig = match self.get_config_by_name(name) {
+            Some(kc) => kc.clone(),
+            None => return Err(Error::ConfigNotFound(String::from(name))),
+        };
+        let hash = get_config_hash(&kubeconfig.path, self.key.as_ref(), self.hash_algorithm)?;
+        self.remember_hash(&kubeconfig.path, &hash)?;
+
+        self.unindex(&kubeconfig.name);
+
+        let mut refreshed = KubeConfig::new(kubeconfig.path.clone(), hash, Some(kubeconfig.name));
+        refreshed.metadata = meta::load(&refreshed.path);
+        self.add(refreshed.clone())?;
+        _ = self.update_current_config();
+
+        return Ok(refreshed);
+    }
+
+    fn forget_default(&self, name: &str) -> Result {
+        let mut settings = settings::load(&self.kubenv_dir);
+        if settings.default.as_deref() != Some(name) {
+            return Ok(());
+        }
+
+        settings.default = None;
+        return settings::save(&self.kubenv_dir, &settings);
+    }
+
+    fn lock(&self) -> Result<lock::Lock> {
+        return lock::Lock::acquire(&self.kubenv_dir.join(".lock"), self.lock_timeout);
+    }
+
+    pub fn find_by_hash_prefix(&self, prefix: &str) -> Result<&KubeConfig> {
+        if let [kc] = self.get_configs_by_hash(prefix).as_slice() {
+            return Ok(*kc);
+        }
+        if prefix.is_empty() {
+            return Err(Error::HashNotFound(String::from(prefix)));
+        }
+
+        let mut candidates: Vec<&KubeConfig> = self
+            .configs
+            .iter()
+            .filter(|kc| kc.hash.starts_with(prefix))
+            .collect();
+        return match candidates.len() {
+            0 => Err(Error::HashNotFound(String::from(prefix))),
+            1 => Ok(candidates.remove(0)),
+            _ => Err(Error::AmbiguousHash {
+                prefix: String::from(prefix),
+                candidates: candidates
+                    .iter()
+                    .map(|kc| format!("{} ({})", kc.hash, kc.name))
+                    .collect(),
+            }),
+        };
+    }
+
+    fn read_config_file(&self) -> Result<Option<serde_yaml::Value>> {
+        let config_file = self.config_file();
+        if !config_file.is_file() {
+            return Ok(None);
+        }
+        let content = match fs::read(&config_file) {
+            Ok(c) => c,
+            Err(msg) => return Err(Error::io("Cannot read file", config_file, msg)),
+        };
+
+        return Ok(Some(yaml::parse(&content)?));
+    }
+
+    fn set_kubeconfig(&mut self, value: &OsStr) {
+        let mut paths: Vec<PathBuf> = env::split_paths(value)
+            .filter(|path| !path.as_os_str().is_empty())
+            .collect();
+        match paths.len() {
+            0 => {}
+            1 => self.config_file = paths.pop(),
+            _ => self.kubeconfig_paths = Some(value.to_string_lossy().into_owned()),
+        }
+    }
+
+    // The config file, as long as KUBECONFIG names a single one
+    fn apply_target(&self) -> Result<PathBuf> {
+        return match &self.kubeconfig_paths {
+            Some(value) => Err(Error::MultipleKubeconfigPaths(value.clone())),
+            None => Ok(self.config_file()),
+        };
+    }
+
+    // Checked even with `force`, applying the live entry, or a stored config
+    // that `KUBECONFIG` points at, would copy or link the config file onto itself
+    fn check_source(&self, kubeconfig: &KubeConfig, config_file: &Path) -> Result {
+        if !kubeconfig.is_managed() || is_same_file(&kubeconfig.path, config_file) {
+            return Err(Error::ApplyLive(kubeconfig.name.clone()));
+        }
+
+        return Ok(());
+    }
+
+    fn check_apply(&self, kubeconfig: &KubeConfig, config_file: &Path) -> Result {
+        let hash = match get_file_hash(config_file, self.hash_algorithm) {
+            Ok(h) => h,
+            Err(_) => return Ok(()),
+        };
+        if hash == kubeconfig.hash {
+            return Err(Error::AlreadyApplied(kubeconfig.name.clone()));
+        }
+        // Overwriting a config that was never imported would lose it
+        let managed = self
+            .get_configs_by_hash(&hash)
+            .iter()
+            .any(|kc| kc.is_managed());
+        if !managed {
+            return Err(Error::UnmanagedConfig(config_file.to_path_buf()));
+        }
+
+        return Ok(());
+    }
+
+    // The link is created next to the config file and renamed over it, so
+    // the existing config survives if symlinks are not permitted
+    fn link_config(&self, target: &Path, config_file: &Path) -> Result {
+        // A link to itself would replace the config file with a dangling loop
+        if is_same_file(target, config_file) {
+            return Err(Error::io(
+                "Cannot link config file to itself",
+                config_file,
+                io::Error::from(io::ErrorKind::InvalidInput),
+            ));
+        }
+        let link_file = config_file.with_file_name(".config.kubenv-link");
+        _ = fs::remove_file(&link_file);
+        if let Err(msg) = symlink(target, &link_file) {
+            return Err(Error::io("Cannot create symlink", link_file, msg));
+        }
+        if let Err(msg) = fs::rename(&link_file, config_file) {
+            _ = fs::remove_file(&link_file);
+            return Err(Error::io("Cannot replace config file", config_file, msg));
+        }
+
+        return Ok(());
+    }
+
+    fn backup_file(&self) -> PathBuf {
+        let config_file = self.config_file();
+        let mut file_name = match config_file.file_name() {
+            Some(name) => name.to_os_string(),
+            None => OsString::from("config"),
+        };
+        file_name.push(".bak");
+
+        return config_file.with_file_name(file_name);
+    }
+
+    fn backup(&self) -> Result {
+        let config_file = self.config_file();
+        if !config_file.is_file() {
+            return Ok(());
+        }
+
+        return copy_atomic(&config_file, &self.backup_file());
+    }
+
+    // The prefix is extended until no other config uses it as a name
+    fn hash_prefix_name(&self, hash: &str) -> String {
+        let mut len = self.name_prefix_len.min(hash.len());
+        while len < hash.len() && self.get_managed_by_name(&hash[..len]).is_some() {
+            len += 1;
+        }
+
+        return String::from(&hash[..len]);
+    }
+
+    // The live entry only stands in for an unmanaged config file, so storing
+    // that file under its name or content is allowed
+    fn get_managed_by_name(&self, name: &str) -> Option<&KubeConfig> {
+        return self
+            .configs_by_name
+            .get(&self.name_key(name))
+            .filter(|kc| kc.is_managed());
+    }
+
+    fn new_config_name(&self, name: Option<String>, hash: &str) -> Result<String> {
+        let duplicate = self
+            .get_configs_by_hash(hash)
+            .into_iter()
+            .find(|kc| kc.is_managed());
+        if let Some(kc) = duplicate {
+            return Err(Error::DuplicateHash(kc.name.clone()));
+        }
+        let name = match name {
+            Some(n) => {
+                validate_name(&n)?;
+                if let Some(kc) = self.get_managed_by_name(&n) {
+                    return Err(Error::DuplicateName(kc.name.clone()));
+                }
+                n
+            }
+            None => self.hash_prefix_name(hash),
+        };
+
+        return Ok(name);
+    }
+
+    // With `overwrite` an existing config keeps its name and file, and only
+    // the other configs are checked for the same content
+    fn content_target(
+        &self,
+        name: Option<String>,
+        hash: &str,
+        overwrite: bool,
+    ) -> Result<(String, PathBuf)> {
+        if let (true, Some(kc)) = (
+            overwrite,
+            name.as_ref()
+                .and_then(|n| self.configs_by_name.get(&self.name_key(n))),
+        ) {
+            if !kc.is_managed() {
+                return Err(Error::NotManaged(kc.name.clone()));
+            }
+            let duplicate = self
+                .get_configs_by_hash(hash)
+                .into_iter()
+                .find(|other| other.is_managed() && other.name != kc.name);
+            if let Some(other) = duplicate {
+                return Err(Error::DuplicateHash(other.name.clone()));
+            }
+            return Ok((kc.name.clone(), kc.path.clone()));
+        }
+        let name = self.new_config_name(name, hash)?;
+        let kubeconfig_path = self.config_path(&name);
+
+        return Ok((name, kubeconfig_path));
+    }
+
+    fn config_suffix(&self, file_name: &str) -> Option<String> {
+        let stored = storage_suffix(Path::new(file_name));
+        let file_name = &file_name[..file_name.len() - stored.len()];
+        for extension in &self.extensions {
+            let suffix = format!(".{}", extension);
+            if file_name.len() > suffix.len() && file_name.ends_with(&suffix) {
+                return Some(format!("{}{}", suffix, stored));
+            }
+        }
+
+        return None;
+    }
+
+    // Configs in nested directories are named after their path relative to
+    // the kubenv directory, with the components joined by '_'
+    fn config_name(&self, path: &Path) -> Option<String> {
+        let file_name = path.file_name()?.to_str()?;
+        let suffix = self.config_suffix(file_name)?;
+        let mut parts: Vec<&str> = vec![];
+        if let Some(parent) = path.strip_prefix(&self.kubenv_dir).ok()?.parent() {
+            for component in parent.components() {
+                parts.push(component.as_os_str().to_str()?);
+            }
+        }
+        parts.push(&file_name[..file_name.len() - suffix.len()]);
+
+        return Some(parts.join("_"));
+    }
+
+    // Encryption takes precedence, an encrypted config is not compressed too
+    fn config_path(&self, name: &str) -> PathBuf {
+        let suffix = match (self.encrypt, self.compress) {
+            (true, _) => crypt::SUFFIX,
+            (false, true) => gzip::SUFFIX,
+            (false, false) => "",
+        };
+
+        return self
+            .kubenv_dir
+            .join(format!("{}.{}{}", name, self.extensions[0], suffix));
+    }
+
+    fn update_configs(&mut self) -> Result<SyncReport> {
+        // Fails early when the algorithm was left out of the build, instead of
+        // skipping every config
+        hash::digest(self.hash_algorithm, &[])?;
+        let mut config_files: Vec<PathBuf> = vec![];
+        if let Err(msg) = scan_dir(&self.kubenv_dir, self.recursive, &mut config_files) {
+            return Err(Error::io(
+                "Cannot read files from directory",
+                &self.kubenv_dir,
+                msg,
+            ));
+        }
+
+        self.configs.clear();
+        self.configs_by_name.clear();
+        self.configs_by_hash.clear();
+        self.aliases = aliases::load(&self.kubenv_dir);
+        let mut report = SyncReport {
+            loaded: 0,
+            skipped: 0,
+            broken_links: vec![],
+            current: None,
+        };
+        let backup_file = self.backup_file();
+        for path in config_files {
+            if path == backup_file {
+                continue;
+            }
+            let name = match self.config_name(&path) {
+                Some(n) => n,
+                None => continue,
+            };
+            // Symlinks are followed, so one file can be stored under several
+            // names, wherever it is. Broken ones are reported instead
+            if is_broken_link(&path) {
+                report.broken_links.push(path);
+                continue;
+            }
+            if !path.is_file() {
+                continue;
+            }
+            // An empty file would otherwise be applied over the kube config
+            if fs::metadata(&path).map_or(true, |metadata| metadata.len() == 0) {
+                report.skipped += 1;
+                continue;
+            }
+            let metadata = meta::load(&path);
+            // Without the key an encrypted config is known by its recorded hash
+            let hash = match (&metadata.hash, self.key.is_some()) {
+                (Some(hash), false) if crypt::is_encrypted(&path) => Ok(hash.clone()),
+                _ => get_config_hash(&path, self.key.as_ref(), self.hash_algorithm),
+            };
+            let hash = match hash {
+                Ok(h) => h,
+                Err(_) => {
+                    report.skipped += 1;
+                    continue;
+                }
+            };
+            let mut kubeconfig = KubeConfig::new(path, hash, Some(name));
+            kubeconfig.metadata = metadata;
+            match self.add(kubeconfig) {
+                Ok(()) => report.loaded += 1,
+                Err(_) => report.skipped += 1,
+            }
         }
-        if let Some(kc) = self.get_config_by_hash(&kubeconfig.hash) {
-            return Err(format!("Config already exists with name '{}'", kc.name));
+
+        return Ok(report);
+    }
+
+    fn remember_hash(&self, path: &Path, hash: &str) -> Result {
+        if !crypt::is_encrypted(path) {
+            return Ok(());
         }
+        let mut metadata = meta::load(path);
+        metadata.hash = Some(String::from(hash));
 
-        // Add to self.configs
-        let kubeconfig_tmp = kubeconfig.clone();
-        let len = self.configs.len();
-        let mut index = 0;
-        if len > 0 {
-            index = len - 1;
-            while index > 0 && kubeconfig_tmp < self.configs[index - 1] {
-                index -= 1;
+        return meta::save(path, &metadata);
+    }
+
+    // Read from the file itself, as the indexed current config may be stale
+    fn applied_name(&self, config_file: &Path) -> Option<String> {
+        let hash = get_file_hash(config_file, self.hash_algorithm).ok()?;
+        let kubeconfig = self
+            .get_configs_by_hash(&hash)
+            .into_iter()
+            .find(|kc| kc.is_managed())?;
+
+        return Some(kubeconfig.name.clone());
+    }
+
+    fn update_current_config(&mut self) -> Result {
+        self.current_config = None;
+        let current_config_file = self.config_file();
+        if !current_config_file.is_file() {
+            return Ok(());
+        }
+
+        let hash = get_file_hash(&current_config_file, self.hash_algorithm)?;
+        if let Some(kc) = self.get_config_by_hash(&hash) {
+            self.current_config = Some(kc.clone());
+            return Ok(());
+        }
+        let name = self.hash_prefix_name(&hash);
+        let mut kubeconfig = KubeConfig::new(current_config_file, hash, Some(name));
+        kubeconfig.origin = Origin::Live;
+        self.current_config = Some(kubeconfig.clone());
+        _ = self.add(kubeconfig);
+
+        return Ok(());
+    }
+
+    fn emit(&self, event: Event) {
+        self.event_sink.emit(&event);
+    }
+
+    // Key of a name in `configs_by_name`
+    fn name_key(&self, name: &str) -> String {
+        return match self.ignore_case {
+            true => name.to_lowercase(),
+            false => String::from(name),
+        };
+    }
+
+    fn unindex(&mut self, name: &str) -> Option<KubeConfig> {
+        let kubeconfig = self.configs_by_name.remove(&self.name_key(name))?;
+        self.configs.retain(|kc| kc.name != kubeconfig.name);
+        if let Some(kubeconfigs) = self.configs_by_hash.get_mut(&kubeconfig.hash) {
+            kubeconfigs.retain(|kc| kc.name != kubeconfig.name);
+            if kubeconfigs.is_empty() {
+                self.configs_by_hash.remove(&kubeconfig.hash);
             }
         }
+
+        return Some(kubeconfig);
+    }
+
+    fn add(&mut self, kubeconfig: KubeConfig) -> Result {
+        if let Some(kc) = self.configs_by_name.get(&self.name_key(&kubeconfig.name)) {
+            return Err(Error::DuplicateName(kc.name.clone()));
+        }
+
+        // Add to self.configs
+        let kubeconfig_tmp = kubeconfig.clone();
+        let index = self.configs.partition_point(|kc| *kc < kubeconfig_tmp);
         self.configs.insert(index, kubeconfig_tmp);
 
         // Add to self.configs_by_name
         let kubeconfig_tmp = kubeconfig.clone();
         self.configs_by_name
-            .insert(kubeconfig_tmp.name.clone(), kubeconfig_tmp);
+            .insert(self.name_key(&kubeconfig_tmp.name), kubeconfig_tmp);
 
-        // Add to self.configs_by_hash
-        let kubeconfig_tmp = kubeconfig.clone();
-        self.configs_by_hash
-            .insert(kubeconfig_tmp.hash.clone(), kubeconfig_tmp);
+        // Add to self.configs_by_hash, several names may share the same content
+        let kubeconfigs = self
+            .configs_by_hash
+            .entry(kubeconfig.hash.clone())
+            .or_default();
+        let index = kubeconfigs.partition_point(|kc| *kc < kubeconfig);
+        kubeconfigs.insert(index, kubeconfig);
 
         return Ok(());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let id = COUNTER.fetch_add(1, atomic::Ordering::SeqCst);
+            let path = env::temp_dir().join(format!("kubenv-test-{}-{}", std::process::id(), id));
+            _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+
+            return Self(path);
+        }
+
+        fn path(&self) -> &Path {
+            return &self.0;
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn kubeconfig(cluster: &str) -> String {
+        return format!(
+            "apiVersion: v1\nkind: Config\nclusters:\n- name: {0}\n  cluster:\n    server: https://{0}.example\ncontexts:\n- name: {0}\n  context:\n    cluster: {0}\n    user: {0}\nusers:\n- name: {0}\n  user: {{}}\ncurrent-context: {0}\n",
+            cluster,
+        );
+    }
+
+    fn kubenv(dir: &TempDir) -> KubEnv {
+        let kube_dir = dir.path().join("kube");
+        fs::create_dir_all(&kube_dir).unwrap();
+        let mut kubenv = KubEnv::new(Some(dir.path().join("kubenv")), Some(kube_dir)).unwrap();
+        kubenv.sync().unwrap();
+
+        return kubenv;
+    }
+
+    fn store(kubenv: &mut KubEnv, name: &str, cluster: &str) {
+        let content = kubeconfig(cluster);
+        kubenv
+            .set_content(
+                Some(String::from(name)),
+                &mut BufReader::new(content.as_bytes()),
+                true,
+                false,
+            )
+            .unwrap();
+        kubenv.reload().unwrap();
+    }
+
+    fn write_live(kubenv: &KubEnv, cluster: &str) {
+        fs::write(kubenv.config_file(), kubeconfig(cluster)).unwrap();
+    }
+
+    #[test]
+    fn unmanaged_config_file_can_be_added() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        write_live(&kubenv, "live");
+        kubenv.reload().unwrap();
+        assert!(!kubenv.current_config().unwrap().is_managed());
+
+        let config_file = kubenv.config_file();
+        kubenv
+            .import_from_path(Some(String::from("live")), &config_file, true, false)
+            .unwrap();
+        kubenv.reload().unwrap();
+
+        let current = kubenv.current_config().unwrap();
+        assert_eq!(current.name(), "live");
+        assert!(current.is_managed());
+    }
+
+    #[test]
+    fn unmanaged_config_file_keeps_its_generated_name() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        write_live(&kubenv, "live");
+        kubenv.reload().unwrap();
+        let live_name = String::from(kubenv.current_config().unwrap().name());
+
+        let config_file = kubenv.config_file();
+        let stored = kubenv
+            .import_from_path(None, &config_file, true, false)
+            .unwrap();
+
+        assert_eq!(stored.name(), live_name);
+    }
+
+    #[test]
+    fn doctor_passes_once_the_config_file_is_added() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        write_live(&kubenv, "live");
+        kubenv.reload().unwrap();
+        let config_file = kubenv.config_file();
+        let check = |kubenv: &KubEnv| {
+            return kubenv
+                .diagnose()
+                .into_iter()
+                .find(|d| d.check == "current config")
+                .unwrap();
+        };
+
+        let diagnostic = check(&kubenv);
+        assert_eq!(diagnostic.severity, Severity::Warn);
+        let hint = diagnostic.hint.unwrap();
+        assert!(hint.contains(&format!("kubenv add --file {}", config_file.display())));
+
+        kubenv
+            .import_from_path(None, &config_file, true, false)
+            .unwrap();
+        kubenv.reload().unwrap();
+        assert_eq!(check(&kubenv).severity, Severity::Pass);
+    }
+
+    #[test]
+    fn apply_over_a_saved_config_file() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "other", "other");
+        write_live(&kubenv, "live");
+        kubenv.reload().unwrap();
+        assert!(matches!(
+            kubenv.apply("other", false, false),
+            Err(Error::UnmanagedConfig(_)),
+        ));
+
+        let config_file = kubenv.config_file();
+        kubenv
+            .import_from_path(Some(String::from("live")), &config_file, true, false)
+            .unwrap();
+        kubenv.reload().unwrap();
+
+        let previous = kubenv.apply("other", false, false).unwrap();
+        assert_eq!(previous.as_deref(), Some("live"));
+    }
+
+    #[test]
+    fn export_writes_the_stored_content() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "dev", "dev");
+
+        let mut buffer: Vec<u8> = Vec::new();
+        kubenv.export("dev", &mut buffer).unwrap();
+        assert_eq!(buffer, kubeconfig("dev").into_bytes());
+
+        let mut buffer: Vec<u8> = Vec::new();
+        assert!(matches!(
+            kubenv.export("missing", &mut buffer),
+            Err(Error::ConfigNotFound(_)),
+        ));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn previous_config_is_the_second_most_recent_apply() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "a", "a");
+        store(&mut kubenv, "b", "b");
+        assert!(matches!(
+            kubenv.previous_config(),
+            Err(Error::NoPreviousConfig),
+        ));
+
+        // Both applies land in the same second
+        kubenv.apply("a", true, false).unwrap();
+        assert!(matches!(
+            kubenv.previous_config(),
+            Err(Error::NoPreviousConfig),
+        ));
+        kubenv.apply("b", true, false).unwrap();
+        assert_eq!(kubenv.previous_config().unwrap(), "a");
+
+        kubenv.apply("a", true, false).unwrap();
+        assert_eq!(kubenv.previous_config().unwrap(), "b");
+    }
+
+    #[test]
+    fn history_without_sequence_numbers_is_loaded() {
+        let dir = TempDir::new();
+        let kubenv = kubenv(&dir);
+        fs::write(
+            history::path(&kubenv.kubenv_dir),
+            r#"{"a": 1700000000, "b": 1700000100}"#,
+        )
+        .unwrap();
+
+        assert_eq!(kubenv.previous_config().unwrap(), "a");
+        history::record(&kubenv.kubenv_dir, "a").unwrap();
+        assert_eq!(kubenv.previous_config().unwrap(), "b");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn use_context_through_a_link_refreshes_the_stored_config() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        let content = kubeconfig("a").replace(
+            "contexts:\n",
+            "contexts:\n- name: b\n  context:\n    cluster: a\n    user: a\n",
+        );
+        kubenv
+            .set_content(
+                Some(String::from("a")),
+                &mut BufReader::new(content.as_bytes()),
+                true,
+                false,
+            )
+            .unwrap();
+        kubenv.reload().unwrap();
+        kubenv.apply_symlink("a", true, false).unwrap();
+        kubenv.reload().unwrap();
+        let old_hash = String::from(kubenv.get_config_by_name("a").unwrap().hash());
+
+        kubenv.set_current_context("b").unwrap();
+
+        let kubeconfig = kubenv.get_config_by_name("a").unwrap();
+        let hash = get_file_hash(kubeconfig.path(), kubenv.hash_algorithm).unwrap();
+        assert_ne!(hash, old_hash);
+        assert_eq!(kubeconfig.hash(), hash);
+        assert!(kubenv.get_configs_by_hash(&old_hash).is_empty());
+        assert_eq!(kubenv.current_config().unwrap().name(), "a");
+        assert_eq!(
+            kubenv.list_contexts("a").unwrap().current.as_deref(),
+            Some("b")
+        );
+    }
+
+    #[test]
+    fn sync_without_config_file() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "a", "a");
+
+        let report = kubenv.sync().unwrap();
+        assert_eq!(report.loaded, 1);
+        assert!(report.current.is_none());
+        assert!(kubenv.current_config().is_none());
+    }
+
+    #[test]
+    fn sync_with_missing_kube_dir() {
+        let dir = TempDir::new();
+        let mut kubenv = KubEnv::new(
+            Some(dir.path().join("kubenv")),
+            Some(dir.path().join("kube")),
+        )
+        .unwrap();
+
+        let report = kubenv.sync().unwrap();
+        assert_eq!(report.loaded, 0);
+        assert!(kubenv.current_config().is_none());
+        assert!(!dir.path().join("kube").exists());
+    }
+
+    fn dir_entries(path: &Path) -> Vec<OsString> {
+        let mut entries: Vec<OsString> = fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        entries.sort();
+
+        return entries;
+    }
+
+    #[test]
+    fn atomic_write_replaces_the_file() {
+        let dir = TempDir::new();
+        let path = dir.path().join("dev.kubeconfig");
+        fs::write(&path, "old").unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(
+            dir_entries(dir.path()),
+            vec![OsString::from("dev.kubeconfig")]
+        );
+    }
+
+    #[test]
+    fn failed_atomic_write_leaves_nothing_behind() {
+        let dir = TempDir::new();
+        // Renaming over a directory that is not empty fails after the
+        // temporary file was written
+        let path = dir.path().join("dev.kubeconfig");
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("keep"), "").unwrap();
+
+        assert!(matches!(
+            write_atomic(&path, b"content"),
+            Err(Error::Io { .. }),
+        ));
+        assert!(path.is_dir());
+        assert_eq!(
+            dir_entries(dir.path()),
+            vec![OsString::from("dev.kubeconfig")]
+        );
+    }
+
+    fn store_unnamed(kubenv: &mut KubEnv, cluster: &str) -> KubeConfig {
+        let content = kubeconfig(cluster);
+        let kubeconfig = kubenv
+            .set_content(None, &mut BufReader::new(content.as_bytes()), true, false)
+            .unwrap();
+        kubenv.reload().unwrap();
+
+        return kubeconfig;
+    }
+
+    #[test]
+    fn generated_name_uses_the_prefix_len() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir).with_name_prefix_len(4);
+
+        let kubeconfig = store_unnamed(&mut kubenv, "a");
+        assert_eq!(kubeconfig.name(), &kubeconfig.hash()[..4]);
+    }
+
+    #[test]
+    fn generated_name_grows_past_a_taken_prefix() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir).with_name_prefix_len(4);
+        let hash = hash::digest(HashAlgorithm::Sha256, kubeconfig("b").as_bytes()).unwrap();
+        store(&mut kubenv, &hash[..4], "a");
+        store(&mut kubenv, &hash[..5], "c");
+
+        let kubeconfig = store_unnamed(&mut kubenv, "b");
+        assert_eq!(kubeconfig.hash(), hash);
+        assert_eq!(kubeconfig.name(), &hash[..6]);
+    }
+
+    #[test]
+    fn prefix_len_is_at_least_one() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir).with_name_prefix_len(0);
+
+        let kubeconfig = store_unnamed(&mut kubenv, "a");
+        assert_eq!(kubeconfig.name(), &kubeconfig.hash()[..1]);
+    }
+
+    #[test]
+    fn configs_are_sorted_whatever_the_insert_order() {
+        let orders = [
+            ["a", "b", "c", "d", "e"],
+            ["e", "d", "c", "b", "a"],
+            ["c", "a", "e", "b", "d"],
+            ["d", "e", "a", "c", "b"],
+        ];
+        for order in orders {
+            let dir = TempDir::new();
+            let mut kubenv = KubEnv::new(Some(dir.path().to_path_buf()), None).unwrap();
+            for name in order {
+                // Every other config shares its content, so the hash index
+                // holds several names
+                let hash = match name {
+                    "a" | "c" | "e" => String::from("same"),
+                    _ => format!("{}-hash", name),
+                };
+                let path = dir.path().join(format!("{}.kubeconfig", name));
+                kubenv
+                    .add(KubeConfig::new(path, hash, Some(String::from(name))))
+                    .unwrap();
+            }
+
+            let names: Vec<&str> = kubenv.configs().iter().map(|kc| kc.name()).collect();
+            assert_eq!(names, ["a", "b", "c", "d", "e"]);
+            let names: Vec<&str> = kubenv
+                .get_configs_by_hash("same")
+                .iter()
+                .map(|kc| kc.name())
+                .collect();
+            assert_eq!(names, ["a", "c", "e"]);
+        }
+    }
+
+    #[test]
+    fn renaming_the_current_config() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "a", "a");
+        store(&mut kubenv, "b", "b");
+        kubenv.apply("b", true, false).unwrap();
+        kubenv.apply("a", true, false).unwrap();
+        kubenv.reload().unwrap();
+
+        kubenv.rename("a", "c").unwrap();
+        assert_eq!(kubenv.current_config().unwrap().name(), "c");
+        assert!(kubenv.is_current("c"));
+        assert!(kubenv.last_applied("c").is_some());
+        assert!(kubenv.last_applied("a").is_none());
+
+        kubenv.apply("b", true, false).unwrap();
+        assert_eq!(kubenv.previous_config().unwrap(), "c");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn renaming_a_linked_config_moves_the_link() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "a", "a");
+        kubenv.apply_symlink("a", true, false).unwrap();
+        kubenv.reload().unwrap();
+
+        kubenv.rename("a", "c").unwrap();
+        let target = fs::read_link(kubenv.config_file()).unwrap();
+        let renamed = kubenv.get_config_by_name("c").unwrap();
+        assert_eq!(target, fs::canonicalize(renamed.path()).unwrap());
+        kubenv.reload().unwrap();
+        assert_eq!(kubenv.current_config().unwrap().name(), "c");
+    }
+
+    #[test]
+    fn removing_the_live_config_is_refused() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        write_live(&kubenv, "live");
+        kubenv.reload().unwrap();
+        let name = String::from(kubenv.current_config().unwrap().name());
+
+        assert!(matches!(kubenv.remove(&name), Err(Error::NotManaged(_))));
+        let hash = &kubenv.current_config().unwrap().hash;
+        assert!(matches!(
+            kubenv.remove_by_hash(hash),
+            Err(Error::NotManaged(_)),
+        ));
+        assert_eq!(
+            fs::read_to_string(kubenv.config_file()).unwrap(),
+            kubeconfig("live"),
+        );
+    }
+
+    #[test]
+    fn removing_a_stored_config() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "a", "a");
+        let path = kubenv.get_config_by_name("a").unwrap().path().to_path_buf();
+
+        kubenv.remove("a").unwrap();
+        assert!(!path.exists());
+        kubenv.reload().unwrap();
+        assert!(kubenv.get_config_by_name("a").is_none());
+    }
+
+    #[test]
+    fn empty_config_is_skipped_on_sync() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "a", "a");
+        fs::write(dir.path().join("kubenv").join("empty.kubeconfig"), "").unwrap();
+
+        let report = kubenv.sync().unwrap();
+        assert_eq!(report.loaded, 1);
+        assert_eq!(report.skipped, 1);
+        assert!(kubenv.get_config_by_name("empty").is_none());
+        assert!(matches!(
+            kubenv.apply("empty", true, false),
+            Err(Error::ConfigNotFound(_)),
+        ));
+    }
+
+    #[test]
+    fn empty_content_is_not_stored() {
+        let dir = TempDir::new();
+        let kubenv = kubenv(&dir);
+
+        let result = kubenv.set_content(
+            Some(String::from("empty")),
+            &mut BufReader::new(&b""[..]),
+            false,
+            false,
+        );
+        assert!(matches!(result, Err(Error::Validation(_))));
+        assert!(!dir.path().join("kubenv").join("empty.kubeconfig").exists());
+    }
+
+    // Fails with `kind` after `ok` successful calls, every read is first
+    // interrupted once
+    struct Faulty {
+        calls: usize,
+        ok: usize,
+        kind: io::ErrorKind,
+        interrupted: bool,
+    }
+
+    impl Faulty {
+        fn new(ok: usize, kind: io::ErrorKind) -> Self {
+            return Self {
+                calls: 0,
+                ok,
+                kind,
+                interrupted: false,
+            };
+        }
+
+        fn call(&mut self) -> io::Result<()> {
+            if self.calls >= self.ok {
+                return Err(io::Error::from(self.kind));
+            }
+            self.calls += 1;
+
+            return Ok(());
+        }
+    }
+
+    impl Read for Faulty {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if !self.interrupted {
+                self.interrupted = true;
+                return Err(io::Error::from(io::ErrorKind::Interrupted));
+            }
+            self.interrupted = false;
+            self.call()?;
+            buf[0] = b'x';
+
+            return Ok(1);
+        }
+    }
+
+    impl Write for Faulty {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.call()?;
+
+            return Ok(buf.len());
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    #[test]
+    fn copy_returns_the_bytes_copied() {
+        let content: Vec<u8> = (0..=255).cycle().take(BUF_SIZE * 2 + 7).collect();
+        for buf_size in [0, 1, 3, BUF_SIZE] {
+            let mut output: Vec<u8> = Vec::new();
+            let copied =
+                copy_with_buf_size(&mut content.as_slice(), &mut output, buf_size).unwrap();
+            assert_eq!(copied, content.len() as u64);
+            assert_eq!(output, content);
+        }
+
+        let mut output: Vec<u8> = Vec::new();
+        assert_eq!(copy(&mut &b""[..], &mut output).unwrap(), 0);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn copy_retries_interrupted_reads() {
+        let mut reader = Faulty::new(3, io::ErrorKind::UnexpectedEof);
+        let mut output: Vec<u8> = Vec::new();
+
+        assert!(matches!(
+            copy(&mut reader, &mut output),
+            Err(Error::Stream { .. }),
+        ));
+        assert_eq!(output, b"xxx");
+    }
+
+    #[test]
+    fn copy_reports_write_errors() {
+        let mut writer = Faulty::new(1, io::ErrorKind::WriteZero);
+        let content = [b'x'; 8];
+
+        let result = copy_with_buf_size(&mut &content[..], &mut writer, 4);
+        match result {
+            Err(Error::Stream { source, .. }) => {
+                assert_eq!(source.kind(), io::ErrorKind::WriteZero)
+            }
+            _ => panic!("expected a stream error"),
+        }
+    }
+
+    const MALICIOUS_NAMES: [&str; 8] =
+        ["", ".", "..", "../evil", "a/b", "/evil", ".hidden", "a\0b"];
+
+    #[test]
+    fn malicious_names_are_rejected_on_add() {
+        let dir = TempDir::new();
+        let kubenv = kubenv(&dir);
+        let before = dir_entries(dir.path());
+        let content = kubeconfig("a");
+
+        for name in MALICIOUS_NAMES {
+            let result = kubenv.set_content(
+                Some(String::from(name)),
+                &mut BufReader::new(content.as_bytes()),
+                true,
+                false,
+            );
+            assert!(
+                matches!(result, Err(Error::InvalidName { .. })),
+                "{:?}",
+                name,
+            );
+        }
+        assert_eq!(dir_entries(dir.path()), before);
+        assert!(dir_entries(&dir.path().join("kubenv"))
+            .iter()
+            .all(|entry| !entry.to_string_lossy().ends_with(".kubeconfig")));
+    }
+
+    #[test]
+    fn malicious_names_are_rejected_on_rename() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "a", "a");
+
+        for name in MALICIOUS_NAMES {
+            assert!(
+                matches!(kubenv.rename("a", name), Err(Error::InvalidName { .. })),
+                "{:?}",
+                name,
+            );
+        }
+        assert!(kubenv.get_config_by_name("a").unwrap().path().is_file());
+        assert_eq!(dir_entries(dir.path()), vec!["kube", "kubenv"]);
+    }
+
+    fn config_with(name: &str, hash: &str, size: u64, modified: u64) -> KubeConfig {
+        let mut kubeconfig = KubeConfig::new(
+            PathBuf::from(format!("{}.kubeconfig", name)),
+            String::from(hash),
+            Some(String::from(name)),
+        );
+        kubeconfig.size = size;
+        kubeconfig.modified = SystemTime::UNIX_EPOCH + Duration::from_secs(modified);
+
+        return kubeconfig;
+    }
+
+    fn sorted_names(kubenv: &KubEnv, configs: &[KubeConfig], key: SortKey) -> Vec<String> {
+        let mut refs: Vec<&KubeConfig> = configs.iter().collect();
+        kubenv.sort_configs(&mut refs, key);
+
+        return refs.iter().map(|kc| kc.name.clone()).collect();
+    }
+
+    #[test]
+    fn configs_sort_by_each_key() {
+        let dir = TempDir::new();
+        let kubenv = kubenv(&dir);
+        let configs = [
+            config_with("b", "aa", 10, 300),
+            config_with("c", "cc", 30, 100),
+            config_with("a", "bb", 20, 200),
+            // Ties with `a` on size and time, name order is kept
+            config_with("d", "dd", 20, 200),
+        ];
+        history::record(&kubenv.kubenv_dir, "c").unwrap();
+        history::record(&kubenv.kubenv_dir, "a").unwrap();
+
+        assert_eq!(
+            sorted_names(&kubenv, &configs, SortKey::Name),
+            ["a", "b", "c", "d"]
+        );
+        assert_eq!(
+            sorted_names(&kubenv, &configs, SortKey::Hash),
+            ["b", "a", "c", "d"]
+        );
+        assert_eq!(
+            sorted_names(&kubenv, &configs, SortKey::Size),
+            ["c", "a", "d", "b"]
+        );
+        assert_eq!(
+            sorted_names(&kubenv, &configs, SortKey::Modified),
+            ["b", "a", "d", "c"],
+        );
+        let mut by_name: Vec<KubeConfig> = configs.to_vec();
+        by_name.sort();
+        assert_eq!(
+            sorted_names(&kubenv, &by_name, SortKey::LastApplied),
+            ["a", "c", "b", "d"],
+        );
+        assert_eq!(SortKey::default(), SortKey::Name);
+    }
+
+    #[test]
+    fn unreadable_history_sorts_by_name() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "b", "b");
+        store(&mut kubenv, "a", "a");
+        fs::write(history::path(&kubenv.kubenv_dir), "not json").unwrap();
+
+        let names: Vec<&str> = kubenv
+            .configs_sorted_by(SortKey::LastApplied)
+            .iter()
+            .map(|kc| kc.name())
+            .collect();
+        assert_eq!(names, ["a", "b"]);
+    }
+
+    // Creating symlinks may need privileges elsewhere
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_configs_are_loaded_and_broken_ones_reported() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        let kubenv_dir = dir.path().join("kubenv");
+        let shared = dir.path().join("shared.yaml");
+        fs::write(&shared, kubeconfig("shared")).unwrap();
+        symlink(&shared, &kubenv_dir.join("linked.kubeconfig")).unwrap();
+        let broken = kubenv_dir.join("broken.kubeconfig");
+        symlink(&dir.path().join("missing.yaml"), &broken).unwrap();
+
+        let report = kubenv.sync().unwrap();
+        assert_eq!(report.loaded, 1);
+        assert_eq!(report.broken_links, vec![broken.clone()]);
+        let linked = kubenv.get_config_by_name("linked").unwrap();
+        assert!(linked.is_managed());
+        assert_eq!(
+            linked.hash(),
+            get_file_hash(&shared, HashAlgorithm::Sha256).unwrap(),
+        );
+        assert!(kubenv.get_config_by_name("broken").is_none());
+
+        let diagnostic = kubenv
+            .diagnose()
+            .into_iter()
+            .find(|d| d.message.contains(&broken.display().to_string()))
+            .unwrap();
+        assert_eq!(diagnostic.severity, Severity::Warn);
+    }
+
+    #[test]
+    fn default_name_of_a_short_hash() {
+        let kubeconfig = KubeConfig::new(PathBuf::from("a.kubeconfig"), String::from("abcd"), None);
+        assert_eq!(kubeconfig.name(), "abcd");
+
+        let kubeconfig = KubeConfig::new(PathBuf::from("a.kubeconfig"), String::new(), None);
+        assert_eq!(kubeconfig.name(), "");
+
+        let kubeconfig = KubeConfig::new(
+            PathBuf::from("a.kubeconfig"),
+            String::from("0123456789abcdef"),
+            None,
+        );
+        assert_eq!(kubeconfig.name(), "01234567");
+    }
+
+    #[test]
+    fn reload_needs_the_kubenv_dir() {
+        let dir = TempDir::new();
+        let kubenv_dir = dir.path().join("kubenv");
+        let mut kubenv =
+            KubEnv::new(Some(kubenv_dir.clone()), Some(dir.path().to_path_buf())).unwrap();
+
+        match kubenv.reload() {
+            Err(Error::KubenvDirNotFound(path)) => assert_eq!(path, kubenv_dir),
+            _ => panic!("expected the kubenv dir to be missing"),
+        }
+        assert!(!kubenv_dir.exists());
+
+        kubenv.sync().unwrap();
+        assert!(kubenv_dir.is_dir());
+        kubenv.reload().unwrap();
+    }
+
+    #[test]
+    fn reload_picks_up_new_configs() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        fs::write(
+            dir.path().join("kubenv").join("a.kubeconfig"),
+            kubeconfig("a"),
+        )
+        .unwrap();
+        assert!(kubenv.get_config_by_name("a").is_none());
+
+        let report = kubenv.reload().unwrap();
+        assert_eq!(report.loaded, 1);
+        assert!(kubenv.get_config_by_name("a").is_some());
+    }
+
+    #[test]
+    fn live_config_cannot_be_applied() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        write_live(&kubenv, "live");
+        kubenv.reload().unwrap();
+        let name = String::from(kubenv.current_config().unwrap().name());
+
+        for force in [false, true] {
+            assert!(matches!(
+                kubenv.apply(&name, force, true),
+                Err(Error::ApplyLive(_)),
+            ));
+            assert!(matches!(
+                kubenv.apply_dry_run(&name, force, true),
+                Err(Error::ApplyLive(_)),
+            ));
+        }
+        assert!(!kubenv.backup_file().exists());
+    }
+
+    #[test]
+    fn live_config_survives_a_forced_apply() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        write_live(&kubenv, "live");
+        kubenv.reload().unwrap();
+        let name = String::from(kubenv.current_config().unwrap().name());
+
+        assert!(kubenv.apply(&name, true, true).is_err());
+        assert_eq!(
+            fs::read_to_string(kubenv.config_file()).unwrap(),
+            kubeconfig("live"),
+        );
+    }
+
+    #[test]
+    fn stored_config_used_as_config_file_is_not_applied() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "a", "a");
+        let path = kubenv.get_config_by_name("a").unwrap().path().to_path_buf();
+        kubenv.config_file = Some(path.clone());
+        kubenv.reload().unwrap();
+
+        assert!(matches!(
+            kubenv.apply("a", true, false),
+            Err(Error::ApplyLive(_)),
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), kubeconfig("a"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn live_config_survives_a_forced_link() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        write_live(&kubenv, "live");
+        kubenv.reload().unwrap();
+        let name = String::from(kubenv.current_config().unwrap().name());
+        let config_file = kubenv.config_file();
+
+        assert!(matches!(
+            kubenv.apply_symlink(&name, true, true),
+            Err(Error::ApplyLive(_)),
+        ));
+        assert!(matches!(
+            kubenv.link_config(&config_file, &config_file),
+            Err(Error::Io { .. }),
+        ));
+        assert!(!fs::symlink_metadata(&config_file)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(
+            fs::read_to_string(&config_file).unwrap(),
+            kubeconfig("live")
+        );
+    }
+
+    #[test]
+    fn multiple_kubeconfig_paths_only_fail_writes() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "a", "a");
+        let paths = env::join_paths([dir.path().join("one"), dir.path().join("two")]).unwrap();
+        kubenv.set_kubeconfig(&paths);
+
+        let report = kubenv.sync().unwrap();
+        assert_eq!(report.loaded, 1);
+        let names: Vec<&str> = kubenv.config_names().collect();
+        assert_eq!(names, ["a"]);
+        assert_eq!(kubenv.config_file(), dir.path().join("kube").join("config"));
+        assert!(matches!(
+            kubenv.apply("a", false, false),
+            Err(Error::MultipleKubeconfigPaths(_)),
+        ));
+        assert!(matches!(
+            kubenv.apply_dry_run("a", false, false),
+            Err(Error::MultipleKubeconfigPaths(_)),
+        ));
+        assert!(matches!(
+            kubenv.set_current_context("a"),
+            Err(Error::MultipleKubeconfigPaths(_)),
+        ));
+    }
+
+    #[test]
+    fn single_kubeconfig_path_is_the_config_file() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "a", "a");
+        let path = dir.path().join("custom");
+        kubenv.set_kubeconfig(path.as_os_str());
+
+        kubenv.apply("a", false, false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), kubeconfig("a"));
+    }
+
+    #[test]
+    fn export_file_replaces_the_destination() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "dev", "dev");
+        let path = dir.path().join("exported");
+        fs::write(&path, "old").unwrap();
+
+        kubenv.export_file("dev", &path, |_| {}).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), kubeconfig("dev"));
+        assert!(!temp_path(&path).exists());
+    }
+
+    #[test]
+    fn failed_export_file_keeps_the_destination() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "dev", "dev");
+        let path = dir.path().join("exported");
+        fs::write(&path, "old").unwrap();
+
+        assert!(matches!(
+            kubenv.export_file("missing", &path, |_| {}),
+            Err(Error::ConfigNotFound(_)),
+        ));
+        assert!(kubenv
+            .export_file("missing", &dir.path().join("new"), |_| {})
+            .is_err());
+        assert!(!dir.path().join("new").exists());
+
+        // Renaming over a directory that is not empty fails once the content
+        // was written
+        let busy = dir.path().join("busy");
+        fs::create_dir(&busy).unwrap();
+        fs::write(busy.join("keep"), "").unwrap();
+        assert!(matches!(
+            kubenv.export_file("dev", &busy, |_| {}),
+            Err(Error::Io { .. }),
+        ));
+        assert!(busy.join("keep").is_file());
+        assert!(!temp_path(&busy).exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert!(!temp_path(&path).exists());
+    }
+
+    #[test]
+    fn export_progress_counts_bytes_on_disk() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir).with_buf_size(16);
+        store(&mut kubenv, "dev", "dev");
+        let path = dir.path().join("exported");
+        let tmp_path = temp_path(&path);
+
+        let mut updates: Vec<(u64, u64)> = vec![];
+        kubenv
+            .export_file("dev", &path, |written| {
+                updates.push((written, fs::metadata(&tmp_path).unwrap().len()));
+            })
+            .unwrap();
+
+        let total = kubeconfig("dev").len() as u64;
+        assert!(updates.len() > 1);
+        assert!(updates.iter().all(|(written, on_disk)| written == on_disk));
+        assert_eq!(updates.last().unwrap().0, total);
+    }
+
+    #[test]
+    fn default_config_moves_out_of_the_legacy_settings_file() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "a", "a");
+        store(&mut kubenv, "b", "b");
+        let kubenv_dir = dir.path().join("kubenv");
+        fs::write(kubenv_dir.join("kubenv.toml"), "default = \"a\"\n").unwrap();
+        assert_eq!(kubenv.default_config().unwrap().name(), "a");
+
+        kubenv.set_default("b").unwrap();
+        assert_eq!(kubenv.default_config().unwrap().name(), "b");
+        assert!(kubenv_dir.join(".settings.toml").is_file());
+        assert!(!kubenv_dir.join("kubenv.toml").exists());
+    }
+
+    #[test]
+    fn rename_keeps_a_nested_config_in_its_directory() {
+        let dir = TempDir::new();
+        let nested = dir.path().join("kubenv").join("team");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(nested.join("prod.kubeconfig"), kubeconfig("prod")).unwrap();
+        let mut kubenv = kubenv(&dir).with_recursive(true);
+        kubenv.reload().unwrap();
+
+        kubenv.rename("team_prod", "team_staging").unwrap();
+        assert!(nested.join("staging.kubeconfig").is_file());
+        assert!(!nested.join("prod.kubeconfig").exists());
+        kubenv.reload().unwrap();
+        assert!(kubenv.get_config_by_name("team_staging").is_some());
+        assert!(kubenv.get_config_by_name("team_prod").is_none());
+
+        // Dropping the prefix would move the config out of its directory
+        let err = kubenv.rename("team_staging", "staging").unwrap_err();
+        assert!(matches!(err, Error::InvalidName { .. }));
+        assert!(nested.join("staging.kubeconfig").is_file());
+    }
+
+    #[test]
+    fn merge_refuses_same_named_clusters_that_differ() {
+        let dir = TempDir::new();
+        let mut kubenv = kubenv(&dir);
+        store(&mut kubenv, "a", "shared");
+        let other_context =
+            kubeconfig("shared").replace("- name: shared\n  context:", "- name: other\n  context:");
+        let other_server = other_context.replace("https://shared.example", "https://other.example");
+        for (name, content) in [("same", &other_context), ("different", &other_server)] {
+            kubenv
+                .set_content(
+                    Some(String::from(name)),
+                    &mut BufReader::new(content.as_bytes()),
+                    true,
+                    false,
+                )
+                .unwrap();
+        }
+        kubenv.reload().unwrap();
+
+        // Identical clusters and users are combined
+        let names = [String::from("a"), String::from("same")];
+        kubenv.merge(&names, "merged").unwrap();
+
+        let names = [String::from("a"), String::from("different")];
+        let result = kubenv.merge(&names, "conflict");
+        assert!(matches!(result, Err(Error::EntryConflict { .. })));
+        kubenv.reload().unwrap();
+        assert!(kubenv.get_config_by_name("conflict").is_none());
+    }
+}
@@ -0,0 +1,47 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{Error, Result};
+
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    // Advisory lock: whoever creates the file first owns it until the guard is dropped
+    pub fn acquire(path: &Path, timeout: Option<Duration>) -> Result<Self> {
+        let started = Instant::now();
+        loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+            {
+                Ok(_) => {
+                    return Ok(Self {
+                        path: path.to_path_buf(),
+                    })
+                }
+                Err(msg) if msg.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(msg) => return Err(Error::io("Cannot create lock file", path, msg)),
+            }
+
+            // Without a timeout wait until the lock is released
+            match timeout {
+                Some(t) if started.elapsed() >= t => return Err(Error::Busy(path.to_path_buf())),
+                _ => thread::sleep(RETRY_INTERVAL),
+            }
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        _ = fs::remove_file(&self.path);
+    }
+}
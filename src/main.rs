@@ -1,14 +1,26 @@
+#![allow(clippy::needless_return)]
+
+use std::env;
 use std::fs;
-use std::io::{stdin, stdout, BufReader, Read, Write};
-use std::path::PathBuf;
-use std::result;
+use std::io::{stderr, stdin, stdout, BufRead, BufReader, IsTerminal, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
 use std::str;
+use std::time::{Duration, UNIX_EPOCH};
 
-use clap::{Parser, Subcommand};
-use kubenv::KubEnv;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use kubenv::{
+    Error, Event, EventSink, HashAlgorithm, Key, KubEnv, KubeConfig, Result, Severity, SortKey,
+    BUF_SIZE,
+};
+use serde::Deserialize;
+use serde_json::json;
 
-type Result<T = ()> = result::Result<T, String>;
-const BUF_SIZE: usize = 1024;
+const PROGRESS_STEP: u64 = 64 * 1024;
+const FINGERPRINT_LEN: usize = 8;
 
 #[derive(Parser)]
 #[command(name = "KubEnv")]
@@ -20,6 +32,47 @@ struct Cli {
     dir: Option<PathBuf>,
     #[arg(short, long)]
     kube_dir: Option<PathBuf>,
+    #[arg(long, default_value = "config")]
+    config_name: String,
+    #[arg(long = "ext")]
+    extensions: Vec<String>,
+    #[arg(long, default_value_t = 8)]
+    name_len: usize,
+    #[arg(short, long)]
+    recursive: bool,
+    #[arg(long)]
+    case_insensitive: bool,
+    #[arg(long)]
+    compress: bool,
+    #[arg(long)]
+    encrypt: bool,
+    #[arg(long)]
+    hash: Option<HashAlgorithm>,
+    #[arg(long)]
+    key_file: Option<PathBuf>,
+    #[arg(short, long)]
+    quiet: bool,
+    #[arg(short, long)]
+    verbose: bool,
+    #[arg(long, default_value = "10")]
+    lock_timeout: LockTimeout,
+    #[arg(long, default_value_t = 10)]
+    http_timeout: u64,
+    #[arg(long, default_value_t = 0)]
+    http_retries: u32,
+    #[arg(long, default_value_t = BUF_SIZE)]
+    buf_size: usize,
+    #[arg(long, conflicts_with = "dir")]
+    xdg: bool,
+    #[arg(long)]
+    audit_log: Option<PathBuf>,
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+    // Only set from the settings file
+    #[arg(skip)]
+    default_config: Option<String>,
+    #[arg(skip)]
+    kube_dir_from_file: bool,
 
     #[command(subcommand)]
     command: Commands,
@@ -27,153 +80,1852 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    List,
+    List {
+        pattern: Option<String>,
+        #[arg(long)]
+        json: bool,
+        #[arg(short, long)]
+        tag: Option<String>,
+        #[arg(short, long, requires = "tag")]
+        ignore_case: bool,
+        #[arg(short, long, value_enum, default_value_t = SortOrder::Name)]
+        sort: SortOrder,
+        #[arg(long)]
+        no_current: bool,
+        #[arg(short, long)]
+        long: bool,
+    },
+    Current {
+        #[arg(short, long, conflicts_with = "namespace")]
+        context: bool,
+        #[arg(short, long)]
+        namespace: bool,
+    },
+    Status {
+        #[arg(long)]
+        json: bool,
+    },
+    Stats {
+        #[arg(long)]
+        json: bool,
+    },
     Apply {
-        name: String,
+        name: Option<String>,
+        #[arg(short, long)]
+        link: bool,
+        #[arg(short, long)]
+        force: bool,
+        #[arg(long)]
+        no_backup: bool,
+        #[arg(long)]
+        dry_run: bool,
+    },
+    Restore,
+    Default {
+        name: Option<String>,
+        #[arg(long, conflicts_with = "name")]
+        unset: bool,
     },
     Add {
         #[arg(short, long)]
         name: Option<String>,
-        #[arg(short, long)]
+        #[arg(short, long, conflicts_with = "url")]
         file: Option<PathBuf>,
+        #[arg(short, long)]
+        url: Option<String>,
+        #[arg(long = "header", requires = "url")]
+        headers: Vec<String>,
+        #[arg(short, long, conflicts_with = "url")]
+        context: Option<String>,
+        #[arg(long)]
+        no_validate: bool,
+        #[arg(long, requires = "name")]
+        overwrite: bool,
+    },
+    ImportDir {
+        dir: PathBuf,
+        #[arg(long)]
+        no_validate: bool,
     },
     Remove {
         name: String,
+        #[arg(long)]
+        by_hash: bool,
+        #[arg(short, long)]
+        yes: bool,
+    },
+    Clone {
+        src: String,
+        new: String,
+    },
+    Rename {
+        old: String,
+        new: String,
+    },
+    Swap {
+        first: String,
+        second: String,
     },
     Show {
         name: String,
+        #[arg(long)]
+        pretty: bool,
+        #[arg(long)]
+        print_hash: bool,
+        #[arg(long, conflicts_with = "pretty")]
+        verify_hash: Option<String>,
     },
-    Export {
+    Edit {
+        name: String,
+    },
+    Alias {
+        alias: Option<String>,
+        #[arg(conflicts_with = "remove")]
+        name: Option<String>,
+        #[arg(short, long, requires = "alias")]
+        remove: bool,
+    },
+    Tag {
         name: String,
+        tags: Vec<String>,
+    },
+    Export {
+        #[arg(required_unless_present = "all")]
+        name: Option<String>,
+        #[arg(short, long)]
+        file: PathBuf,
+        #[arg(long, conflicts_with_all = ["name", "print_hash", "verify_hash", "progress"])]
+        all: bool,
+        #[arg(long)]
+        print_hash: bool,
+        #[arg(long)]
+        verify_hash: Option<String>,
+        #[arg(long)]
+        progress: bool,
+    },
+    ExportAll {
+        dir: PathBuf,
+    },
+    Verify {
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        name: Option<String>,
         #[arg(short, long)]
+        all: bool,
+    },
+    Prune {
+        #[arg(long)]
+        delete: bool,
+        #[arg(short, long, requires = "delete")]
+        yes: bool,
+        #[arg(long, default_value_t = 3)]
+        timeout: u64,
+    },
+    IsApplied {
+        name: String,
+    },
+    Archive {
+        file: PathBuf,
+        #[arg(long)]
+        progress: bool,
+    },
+    Unarchive {
         file: PathBuf,
     },
+    Diff {
+        a: String,
+        b: String,
+    },
+    Info {
+        name: String,
+    },
+    Contexts {
+        name: String,
+    },
+    MergeCurrent {
+        name: String,
+    },
+    UseContext {
+        context: String,
+    },
+    Duplicates,
+    Normalize {
+        #[arg(long)]
+        dry_run: bool,
+    },
+    Doctor,
+    Watch,
+    Completions {
+        shell: Shell,
+    },
+    Merge {
+        #[arg(required = true)]
+        names: Vec<String>,
+        #[arg(long)]
+        into: String,
+    },
 }
 
-fn print_error(message: String) {
-    eprintln!("[ERROR] {}", message);
+#[derive(Clone, Copy, Deserialize, PartialEq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    Text,
+    Json,
+    Yaml,
 }
 
-fn reader_to_writer(reader: &mut dyn Read, writer: &mut dyn Write) -> Result {
-    let mut buffer = vec![0; BUF_SIZE];
-    let mut read_result = reader.read(&mut buffer);
-    while let Ok(count) = read_result {
-        if count == 0 {
-            break;
-        }
-        if let Err(msg) = writer.write(&buffer[..count]) {
-            return Err(format!("Cannot write: {}", msg));
+#[derive(Clone, Copy, ValueEnum)]
+enum SortOrder {
+    Name,
+    Hash,
+    Size,
+    #[value(alias = "recent")]
+    Modified,
+    LastApplied,
+}
+
+fn print_error(error: Error) {
+    eprintln!("[ERROR] {}", error);
+    if let Error::UnmanagedConfig(path) = &error {
+        eprintln!(
+            "[HINT] Save it first with 'kubenv add --file {}' or apply with --force",
+            path.display(),
+        );
+    }
+    if let Error::ApplyLive(_) = &error {
+        eprintln!("[HINT] Store it with 'kubenv add --file <path>' to apply it by a new name");
+    }
+    if let Error::Busy(path) = &error {
+        eprintln!(
+            "[HINT] If no other kubenv is running, remove '{}'",
+            path.display(),
+        );
+    }
+}
+
+fn print_success(quiet: bool, message: &str) {
+    if !quiet {
+        println!("{}", message);
+    }
+}
+
+fn print_rows(rows: &[(&str, String)]) {
+    for (key, value) in rows {
+        println!("{:<12} {}", format!("{}:", key), value);
+    }
+}
+
+// The per-command --json flag is a shorthand for --output json
+fn output_format(output: OutputFormat, json: bool) -> OutputFormat {
+    return match json {
+        true => OutputFormat::Json,
+        false => output,
+    };
+}
+
+fn print_structured(output: OutputFormat, value: &serde_json::Value) -> Result {
+    match output {
+        OutputFormat::Json => println!("{}", value),
+        OutputFormat::Yaml => match serde_yaml::to_string(value) {
+            Ok(content) => print!("{}", content),
+            Err(msg) => return Err(Error::Yaml(msg)),
+        },
+        OutputFormat::Text => {}
+    }
+
+    return Ok(());
+}
+
+fn print_info(message: &str) {
+    eprintln!("[INFO] {}", message);
+}
+
+fn print_warning(message: &str) {
+    eprintln!("[WARNING] {}", message);
+}
+
+fn expand_vars(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(index) = rest.find('$') {
+        result.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+        let (name, tail) = match rest.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", rest),
+            },
+            None => {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            }
         };
-        read_result = reader.read(&mut buffer);
+        // Unknown variables and a lone '$' are kept as written
+        match env::var(name) {
+            Ok(value) if !name.is_empty() => result.push_str(&value),
+            _ => {
+                result.push('$');
+                result.push_str(&rest[..rest.len() - tail.len()]);
+            }
+        }
+        rest = tail;
+    }
+    result.push_str(rest);
+
+    return result;
+}
+
+fn expand_path(path: &Path) -> Result<PathBuf> {
+    let path = match path.to_str() {
+        Some(p) => expand_vars(p),
+        None => return Ok(path.to_path_buf()),
+    };
+    if path != "~" && !path.starts_with("~/") {
+        return Ok(PathBuf::from(path));
+    }
+
+    let home_dir = match home::home_dir() {
+        Some(dir) => dir,
+        None => return Err(Error::HomeDirNotFound),
+    };
+    return match path.strip_prefix("~/") {
+        Some(rest) => Ok(home_dir.join(rest)),
+        None => Ok(home_dir),
+    };
+}
+
+// Persistent defaults for the global flags, read from kubenv.toml
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    kubenv_dir: Option<PathBuf>,
+    kube_dir: Option<PathBuf>,
+    output: Option<OutputFormat>,
+    config_name: Option<String>,
+    default_config: Option<String>,
+    case_insensitive: Option<bool>,
+    hash: Option<String>,
+}
+
+fn config_file_path() -> Option<PathBuf> {
+    let mut candidates: Vec<PathBuf> = vec![];
+    if let Some(path) = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
+        if path.is_absolute() {
+            candidates.push(path.join("kubenv").join("kubenv.toml"));
+        }
+    }
+    if let Some(home_dir) = home::home_dir() {
+        candidates.push(home_dir.join(".config").join("kubenv").join("kubenv.toml"));
+    }
+
+    return candidates.into_iter().find(|path| path.is_file());
+}
+
+fn load_file_config() -> Result<(PathBuf, FileConfig)> {
+    let path = match config_file_path() {
+        Some(p) => p,
+        None => return Ok((PathBuf::new(), FileConfig::default())),
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(msg) => return Err(Error::io("Cannot read file", path, msg)),
+    };
+
+    return match toml::from_str(&content) {
+        Ok(config) => Ok((path, config)),
+        Err(msg) => Err(Error::ConfigFile {
+            path,
+            message: msg.to_string(),
+        }),
+    };
+}
+
+// File values only replace built-in defaults, flags given on the command line win
+fn apply_file_config(cli: &mut Cli, matches: &ArgMatches) -> Result {
+    let (path, config) = load_file_config()?;
+    let is_default = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+
+    if cli.dir.is_none() && !cli.xdg && !xdg_enabled() {
+        cli.dir = config.kubenv_dir;
+    }
+    if let (None, Some(kube_dir)) = (&cli.kube_dir, config.kube_dir) {
+        cli.kube_dir = Some(kube_dir);
+        cli.kube_dir_from_file = true;
+    }
+    if let Some(output) = config.output {
+        if is_default("output") {
+            cli.output = output;
+        }
+    }
+    if let Some(case_insensitive) = config.case_insensitive {
+        if is_default("case_insensitive") {
+            cli.case_insensitive = case_insensitive;
+        }
+    }
+    if let (None, Some(hash)) = (cli.hash, config.hash) {
+        match hash.parse() {
+            Ok(algorithm) => cli.hash = Some(algorithm),
+            Err(message) => return Err(Error::ConfigFile { path, message }),
+        }
     }
-    if let Err(msg) = read_result {
-        return Err(format!("Cannot read: {}", msg));
+    if let Some(config_name) = config.config_name {
+        if is_default("config_name") {
+            cli.config_name = config_name;
+        }
     }
+    cli.default_config = config.default_config;
 
     return Ok(());
 }
 
-fn main() {
-    let cli = Cli::parse();
+// Seconds to wait for the lock, or `inf` to wait as long as it takes
+#[derive(Clone, Copy, Debug)]
+struct LockTimeout(Option<Duration>);
+
+impl str::FromStr for LockTimeout {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        if value == "inf" {
+            return Ok(Self(None));
+        }
+
+        return match value.parse::<u64>() {
+            Ok(secs) => Ok(Self(Some(Duration::from_secs(secs)))),
+            Err(_) => Err(format!("'{}' is not a number of seconds or 'inf'", value)),
+        };
+    }
+}
+
+// Appends every event as a JSON line, a failed write only warns
+struct AuditLog {
+    path: PathBuf,
+}
 
-    let mut kubenv = match KubEnv::new(cli.dir, cli.kube_dir) {
+impl EventSink for AuditLog {
+    fn emit(&self, event: &Event) {
+        let timestamp = match event.timestamp.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs(),
+            Err(_) => 0,
+        };
+        let line = json!({
+            "operation": event.operation.as_str(),
+            "name": event.name,
+            "hash": event.hash,
+            "from": event.from,
+            "timestamp": timestamp,
+        });
+        let result = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", line));
+        if let Err(msg) = result {
+            print_warning(&format!(
+                "Cannot write audit log '{}': {}",
+                self.path.display(),
+                msg
+            ));
+        }
+    }
+}
+
+fn xdg_enabled() -> bool {
+    return match env::var("KUBENV_XDG") {
+        Ok(value) => !matches!(value.as_str(), "" | "0" | "false"),
+        Err(_) => false,
+    };
+}
+
+// --key-file wins over KUBENV_KEY, which wins over KUBENV_KEY_FILE
+fn load_key(cli: &Cli) -> Result<Option<Key>> {
+    let path = match (&cli.key_file, env::var("KUBENV_KEY")) {
+        (Some(path), _) => expand_path(path)?,
+        (None, Ok(value)) if !value.trim().is_empty() => {
+            return Ok(Some(kubenv::parse_key(&value)?))
+        }
+        (None, _) => match env::var_os("KUBENV_KEY_FILE") {
+            Some(value) if !value.is_empty() => PathBuf::from(value),
+            _ => return Ok(None),
+        },
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(msg) => return Err(Error::io("Cannot read file", path, msg)),
+    };
+
+    return Ok(Some(kubenv::parse_key(&content)?));
+}
+
+fn init(cli: &Cli) -> Result<KubEnv> {
+    let dir = match &cli.dir {
+        Some(path) => Some(expand_path(path)?),
+        None if cli.xdg || xdg_enabled() => Some(kubenv::default_kubenv_dir(true)?),
+        None => None,
+    };
+    let kube_dir = match &cli.kube_dir {
+        Some(path) => Some(expand_path(path)?),
+        None => None,
+    };
+    let mut kubenv = KubEnv::new(dir, kube_dir)?
+        .with_config_filename(cli.config_name.clone())
+        .with_extensions(cli.extensions.clone())
+        .with_name_prefix_len(cli.name_len)
+        .with_recursive(cli.recursive)
+        .with_ignore_case(cli.case_insensitive)
+        .with_hash_algorithm(cli.hash.unwrap_or_default())
+        .with_compress(cli.compress)
+        .with_encrypt(cli.encrypt)
+        .with_key(load_key(cli)?)
+        .with_lock_timeout(cli.lock_timeout.0)
+        .with_http_timeout(Duration::from_secs(cli.http_timeout))
+        .with_http_retries(cli.http_retries)
+        .with_buf_size(cli.buf_size);
+    // Only --kube-dir overrides KUBECONFIG, not the settings file
+    if cli.kube_dir.is_none() || cli.kube_dir_from_file {
+        kubenv = kubenv.with_kubeconfig_env();
+    }
+    if let Some(path) = &cli.audit_log {
+        kubenv = kubenv.with_event_sink(AuditLog {
+            path: expand_path(path)?,
+        });
+    }
+    let report = kubenv.sync()?;
+    if !cli.quiet {
+        for path in &report.broken_links {
+            print_warning(&format!("Skipped broken symlink '{}'", path.display()));
+        }
+        for alias in kubenv.alias_clashes() {
+            print_warning(&format!(
+                "Alias '{}' is shadowed by the config with the same name",
+                alias,
+            ));
+        }
+    }
+    if cli.verbose {
+        print_info(&format!(
+            "Loaded {} configs from '{}', skipped {}",
+            report.loaded,
+            kubenv.kubenv_dir().display(),
+            report.skipped,
+        ));
+        match report.current {
+            Some(name) => print_info(&format!("Current config is '{}'", name)),
+            None => print_info("No current config detected"),
+        }
+    }
+
+    return Ok(kubenv);
+}
+
+fn main() -> ExitCode {
+    let matches = Cli::command().get_matches();
+    let mut cli = match Cli::from_arg_matches(&matches) {
+        Ok(c) => c,
+        Err(err) => err.exit(),
+    };
+    if let Commands::Completions { shell } = &cli.command {
+        return completions(*shell);
+    }
+    if let Err(msg) = apply_file_config(&mut cli, &matches) {
+        print_error(msg);
+        return ExitCode::FAILURE;
+    }
+
+    let mut kubenv = match init(&cli) {
         Ok(ke) => ke,
         Err(msg) => {
             print_error(msg);
-            return;
+            return ExitCode::FAILURE;
         }
     };
-    if let Err(msg) = kubenv.sync() {
-        print_error(msg);
-        return;
-    }
 
     let result = match &cli.command {
-        Commands::List => list(&kubenv),
-        Commands::Apply { name } => apply(&kubenv, &name),
-        Commands::Add { name, file } => add(&kubenv, &name, &file),
-        Commands::Remove { name } => remove(&kubenv, &name),
-        Commands::Show { name } => show(&kubenv, &name),
-        Commands::Export { name, file } => export(&kubenv, &name, &file),
-    };
-    if let Err(msg) = result {
-        print_error(msg);
+        Commands::List {
+            pattern,
+            json,
+            tag,
+            ignore_case,
+            sort,
+            no_current,
+            long,
+        } => list(
+            &kubenv,
+            output_format(cli.output, *json),
+            filter_configs(&kubenv, pattern, tag, *ignore_case, *no_current),
+            *sort,
+            *long,
+        ),
+        Commands::Current { context, namespace } => {
+            current(&kubenv, cli.output, *context, *namespace)
+        }
+        Commands::Status { json } => status(&kubenv, output_format(cli.output, *json)),
+        Commands::Stats { json } => stats(&kubenv, output_format(cli.output, *json)),
+        Commands::Apply {
+            name,
+            link,
+            force,
+            no_backup,
+            dry_run,
+        } => match select_config(&kubenv, name, &cli.default_config) {
+            Ok(Some(name)) if *dry_run => apply_dry_run(&kubenv, &name, *link, *force, !no_backup),
+            Ok(Some(name)) => apply(&kubenv, &name, *link, *force, !no_backup, cli.quiet),
+            Ok(None) => Ok(ExitCode::FAILURE),
+            Err(err) => Err(err),
+        },
+        Commands::Restore => restore(&kubenv, cli.quiet),
+        Commands::Default { name, unset } => default(&kubenv, name, *unset, cli.quiet),
+        Commands::Add {
+            name,
+            file,
+            url,
+            headers,
+            context,
+            no_validate,
+            overwrite,
+        } => match url {
+            Some(url) => add_from_url(
+                &kubenv,
+                name,
+                url,
+                headers,
+                !no_validate,
+                *overwrite,
+                cli.quiet,
+            ),
+            None => add(
+                &kubenv,
+                name,
+                file,
+                context,
+                !no_validate,
+                *overwrite,
+                cli.quiet,
+            ),
+        },
+        Commands::ImportDir { dir, no_validate } => {
+            import_dir(&mut kubenv, dir, !no_validate, cli.quiet)
+        }
+        Commands::Remove { name, by_hash, yes } => remove(&kubenv, name, *by_hash, *yes, cli.quiet),
+        Commands::Clone { src, new } => clone(&kubenv, src, new, cli.quiet),
+        Commands::Rename { old, new } => rename(&mut kubenv, old, new, cli.quiet),
+        Commands::Swap { first, second } => swap(&mut kubenv, first, second, cli.quiet),
+        Commands::Show {
+            name,
+            pretty,
+            print_hash,
+            verify_hash,
+        } => show(&kubenv, name, *pretty, *print_hash, verify_hash),
+        Commands::Edit { name } => edit(&mut kubenv, name, cli.quiet),
+        Commands::Alias {
+            alias,
+            name,
+            remove,
+        } => self::alias(&kubenv, alias, name, *remove, cli.quiet),
+        Commands::Tag { name, tags } => tag(&kubenv, name, tags, cli.quiet),
+        Commands::Export {
+            name,
+            file,
+            all,
+            print_hash,
+            verify_hash,
+            progress,
+        } => match (name, all) {
+            (_, true) => export_all(&kubenv, &export_path(file, true), cli.quiet),
+            (Some(name), false) => export(
+                &kubenv,
+                name,
+                &export_path(file, false),
+                *print_hash,
+                verify_hash,
+                *progress,
+                cli.quiet,
+            ),
+            (None, false) => Ok(ExitCode::FAILURE),
+        },
+        Commands::ExportAll { dir } => export_all(&kubenv, dir, cli.quiet),
+        Commands::Verify { name, all } => verify(&kubenv, name, *all),
+        Commands::Prune {
+            delete,
+            yes,
+            timeout,
+        } => prune(&kubenv, *delete, *yes, *timeout, cli.quiet),
+        Commands::IsApplied { name } => is_applied(&kubenv, name),
+        Commands::Archive { file, progress } => archive(&kubenv, file, *progress, cli.quiet),
+        Commands::Unarchive { file } => unarchive(&kubenv, file, cli.quiet),
+        Commands::Diff { a, b } => diff(&kubenv, a, b),
+        Commands::Info { name } => info(&kubenv, cli.output, name),
+        Commands::Contexts { name } => contexts(&kubenv, cli.output, name),
+        Commands::Merge { names, into } => merge(&kubenv, names, into, cli.quiet),
+        Commands::MergeCurrent { name } => merge_current(&kubenv, name, cli.quiet),
+        Commands::UseContext { context } => use_context(&mut kubenv, context, cli.quiet),
+        Commands::Duplicates => duplicates(&kubenv),
+        Commands::Normalize { dry_run } => normalize(&mut kubenv, *dry_run, cli.quiet),
+        Commands::Doctor => doctor(&kubenv, cli.output),
+        Commands::Watch => watch(&mut kubenv),
+        Commands::Completions { shell } => Ok(completions(*shell)),
+    };
+    return match result {
+        Ok(code) => code,
+        Err(msg) => {
+            print_error(msg);
+            ExitCode::FAILURE
+        }
+    };
+}
+
+fn filter_configs<'a>(
+    kubenv: &'a KubEnv,
+    pattern: &Option<String>,
+    tag: &Option<String>,
+    ignore_case: bool,
+    no_current: bool,
+) -> Vec<&'a KubeConfig> {
+    let mut configs: Vec<&KubeConfig> = match (tag, ignore_case) {
+        (Some(t), true) => kubenv.configs_by_tag_ignore_case(t),
+        (Some(t), false) => kubenv.configs_by_tag(t),
+        (None, _) => kubenv.configs(),
+    };
+    if let Some(p) = pattern {
+        let matched: Vec<&str> = kubenv.find(p).iter().map(|kc| kc.name()).collect();
+        configs.retain(|kubeconfig| matched.contains(&kubeconfig.name()));
+    }
+    // The kube config is listed even when it was never added to kubenv
+    if no_current {
+        configs.retain(|kubeconfig| kubeconfig.is_managed());
     }
+
+    return configs;
 }
 
-fn list(kubenv: &KubEnv) -> Result {
-    let current_config = kubenv.current_config();
-    for kubeconfig in kubenv.configs() {
+fn list(
+    kubenv: &KubEnv,
+    output: OutputFormat,
+    mut configs: Vec<&KubeConfig>,
+    sort: SortOrder,
+    long: bool,
+) -> Result<ExitCode> {
+    let key = match sort {
+        SortOrder::Name => SortKey::Name,
+        SortOrder::Hash => SortKey::Hash,
+        SortOrder::Size => SortKey::Size,
+        SortOrder::Modified => SortKey::Modified,
+        SortOrder::LastApplied => SortKey::LastApplied,
+    };
+    kubenv.sort_configs(&mut configs, key);
+    if output != OutputFormat::Text {
+        let configs: Vec<serde_json::Value> = configs
+            .iter()
+            .map(|kubeconfig| {
+                json!({
+                    "name": kubeconfig.name(),
+                    "hash": kubeconfig.hash(),
+                    "current": kubenv.is_current(kubeconfig.name()),
+                    "tags": kubeconfig.tags(),
+                    "size": kubeconfig.size(),
+                })
+            })
+            .collect();
+        print_structured(output, &serde_json::Value::Array(configs))?;
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let width = configs
+        .iter()
+        .map(|kubeconfig| kubeconfig.name().len())
+        .max()
+        .unwrap_or(0);
+    for kubeconfig in configs {
         let name = kubeconfig.name();
-        let mut output = format!("  {}", name);
-        if let Some(cf) = current_config {
-            if cf.hash() == kubeconfig.hash() {
-                output = format!("* {}", name);
+        let marker = match kubenv.is_current(name) {
+            true => "*",
+            false => " ",
+        };
+        match long {
+            true => println!(
+                "{} {:<width$}  {:<len$}  {:>10}",
+                marker,
+                name,
+                kubeconfig.fingerprint(FINGERPRINT_LEN),
+                format_size(kubeconfig.size()),
+                width = width,
+                len = FINGERPRINT_LEN,
+            ),
+            false => println!("{} {}", marker, name),
+        }
+    }
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn current(
+    kubenv: &KubEnv,
+    output: OutputFormat,
+    context: bool,
+    namespace: bool,
+) -> Result<ExitCode> {
+    if namespace {
+        let namespace = kubenv.current_namespace()?;
+        if output != OutputFormat::Text {
+            print_structured(output, &json!({ "namespace": namespace }))?;
+        }
+        if kubenv.current_context_name()?.is_none() {
+            return Ok(ExitCode::FAILURE);
+        }
+        if output == OutputFormat::Text {
+            println!("{}", namespace.as_deref().unwrap_or("default"));
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    if context {
+        let name = kubenv.current_context_name()?;
+        if output != OutputFormat::Text {
+            print_structured(output, &json!({ "context": name }))?;
+        }
+        return match name {
+            Some(name) => {
+                if output == OutputFormat::Text {
+                    println!("{}", name);
+                }
+                Ok(ExitCode::SUCCESS)
+            }
+            None => Ok(ExitCode::FAILURE),
+        };
+    }
+
+    let current_config = kubenv.current_config();
+    if output != OutputFormat::Text {
+        let value = match current_config {
+            Some(kubeconfig) => json!({
+                "name": kubeconfig.name(),
+                "hash": kubeconfig.hash(),
+            }),
+            None => serde_json::Value::Null,
+        };
+        print_structured(output, &value)?;
+    }
+    return match current_config {
+        Some(kubeconfig) => {
+            if output == OutputFormat::Text {
+                println!("{}", kubeconfig.name());
             }
+            Ok(ExitCode::SUCCESS)
         }
-        println!("{}", output);
+        None => Ok(ExitCode::FAILURE),
+    };
+}
+
+fn normalize(kubenv: &mut KubEnv, dry_run: bool, quiet: bool) -> Result<ExitCode> {
+    let renamed = match dry_run {
+        true => kubenv.normalize_plan(),
+        false => kubenv.normalize_names()?,
+    };
+    for (old_name, new_name) in &renamed {
+        println!("{} -> {}", old_name, new_name);
+    }
+    if !dry_run {
+        print_success(
+            quiet,
+            &format!("Renamed {} configs successfully", renamed.len()),
+        );
     }
 
-    return Ok(());
+    return Ok(ExitCode::SUCCESS);
 }
 
-fn apply(kubenv: &KubEnv, name: &str) -> Result {
-    kubenv.apply(name)?;
-    println!("Apply config '{}' succesfully", name);
+fn doctor(kubenv: &KubEnv, output: OutputFormat) -> Result<ExitCode> {
+    let diagnostics = kubenv.diagnose();
+    let code = match diagnostics.iter().any(|d| d.severity == Severity::Fail) {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS,
+    };
+    let label = |severity: Severity| match severity {
+        Severity::Pass => "pass",
+        Severity::Warn => "warn",
+        Severity::Fail => "fail",
+    };
+    if output != OutputFormat::Text {
+        let diagnostics: Vec<serde_json::Value> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                json!({
+                    "severity": label(diagnostic.severity),
+                    "check": diagnostic.check,
+                    "message": diagnostic.message,
+                    "hint": diagnostic.hint,
+                })
+            })
+            .collect();
+        print_structured(output, &serde_json::Value::Array(diagnostics))?;
 
-    return Ok(());
+        return Ok(code);
+    }
+
+    for diagnostic in &diagnostics {
+        println!(
+            "[{}] {}: {}",
+            label(diagnostic.severity).to_uppercase(),
+            diagnostic.check,
+            diagnostic.message,
+        );
+        if let Some(hint) = &diagnostic.hint {
+            println!("[HINT] {}", hint);
+        }
+    }
+
+    return Ok(code);
 }
 
-fn remove(kubenv: &KubEnv, name: &str) -> Result {
-    kubenv.remove(&name)?;
-    println!("Remove config '{}' successfully", name);
+fn stats(kubenv: &KubEnv, output: OutputFormat) -> Result<ExitCode> {
+    let stats = kubenv.stats();
+    if output != OutputFormat::Text {
+        let tags: serde_json::Map<String, serde_json::Value> = stats
+            .tags
+            .iter()
+            .map(|(tag, count)| (tag.clone(), json!(count)))
+            .collect();
+        let value = json!({
+            "configs": stats.configs,
+            "current": stats.current,
+            "tags": tags,
+            "bytes": stats.bytes,
+        });
+        print_structured(output, &value)?;
 
-    return Ok(());
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let tags: Vec<String> = stats
+        .tags
+        .iter()
+        .map(|(tag, count)| format!("{} ({})", tag, count))
+        .collect();
+    let tags = match tags.is_empty() {
+        true => String::from("-"),
+        false => tags.join(", "),
+    };
+    let rows = [
+        ("configs", stats.configs.to_string()),
+        ("as current", stats.current.to_string()),
+        ("tags", tags),
+        ("size", format_size(stats.bytes)),
+    ];
+    print_rows(&rows);
+
+    return Ok(ExitCode::SUCCESS);
 }
 
-fn add(kubenv: &KubEnv, name: &Option<String>, path: &Option<PathBuf>) -> Result {
-    let mut reader: BufReader<Box<dyn Read>> = match path {
-        Some(path) => match fs::File::open(path) {
-            Ok(f) => BufReader::with_capacity(BUF_SIZE, Box::new(f)),
-            Err(msg) => {
-                match path.to_str() {
-                    Some(p) => return Err(format!("Cannot open file '{}': {}", p, msg)),
-                    None => return Err(format!("Cannot open file: {}", msg)),
-                };
+fn status(kubenv: &KubEnv, output: OutputFormat) -> Result<ExitCode> {
+    let current_config = kubenv.current_config();
+    let managed = current_config.map(|kubeconfig| kubeconfig.is_managed());
+    let namespace = kubenv.current_namespace().unwrap_or_default();
+    let configs_count = kubenv
+        .configs()
+        .into_iter()
+        .filter(|kubeconfig| kubeconfig.is_managed())
+        .count();
+    if output != OutputFormat::Text {
+        let current = current_config.map(|kubeconfig| {
+            json!({
+                "name": kubeconfig.name(),
+                "hash": kubeconfig.hash(),
+            })
+        });
+        let status = json!({
+            "kubenv_dir": kubenv.kubenv_dir(),
+            "kube_dir": kubenv.kube_dir(),
+            "config_file": kubenv.config_file(),
+            "configs": configs_count,
+            "current": current,
+            "namespace": namespace,
+            "managed": managed,
+        });
+        print_structured(output, &status)?;
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let current = match current_config {
+        Some(kubeconfig) => format!(
+            "{} ({})",
+            kubeconfig.name(),
+            kubeconfig.fingerprint(FINGERPRINT_LEN),
+        ),
+        None => String::from("none"),
+    };
+    let managed = match managed {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "-",
+    };
+    let rows = [
+        ("kubenv dir", kubenv.kubenv_dir().display().to_string()),
+        ("kube dir", kubenv.kube_dir().display().to_string()),
+        ("config file", kubenv.config_file().display().to_string()),
+        ("configs", configs_count.to_string()),
+        ("current", current),
+        (
+            "namespace",
+            namespace.unwrap_or_else(|| String::from("default")),
+        ),
+        ("managed", String::from(managed)),
+    ];
+    print_rows(&rows);
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn apply(
+    kubenv: &KubEnv,
+    name: &str,
+    link: bool,
+    force: bool,
+    backup: bool,
+    quiet: bool,
+) -> Result<ExitCode> {
+    let previous = match link {
+        true => kubenv.apply_symlink(name, force, backup)?,
+        false => kubenv.apply(name, force, backup)?,
+    };
+    match previous {
+        Some(previous) if previous != name => print_success(
+            quiet,
+            &format!("Switched from '{}' to '{}'", previous, name),
+        ),
+        _ => print_success(quiet, &format!("Apply config '{}' succesfully", name)),
+    }
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+// Without a name the default config is used or the config is picked
+// interactively, and '-' goes back to the previously applied config
+fn select_config(
+    kubenv: &KubEnv,
+    name: &Option<String>,
+    default_config: &Option<String>,
+) -> Result<Option<String>> {
+    match name.as_deref() {
+        Some("-") => return kubenv.previous_config().map(Some),
+        Some(n) => return Ok(Some(String::from(n))),
+        None => {}
+    }
+
+    // The default set with `kubenv default` comes before the settings file
+    return match (kubenv.default_config(), default_config) {
+        (Some(kubeconfig), _) => Ok(Some(String::from(kubeconfig.name()))),
+        (None, Some(name)) => Ok(Some(name.clone())),
+        (None, None) => pick_config(kubenv),
+    };
+}
+
+#[cfg(feature = "picker")]
+fn pick_config(kubenv: &KubEnv) -> Result<Option<String>> {
+    use dialoguer::console::Term;
+    use dialoguer::FuzzySelect;
+
+    if !Term::stderr().is_term() {
+        return Err(Error::NotInteractive);
+    }
+    // The live entry cannot be applied, so it is not offered
+    let names: Vec<&str> = kubenv
+        .configs()
+        .into_iter()
+        .filter(|kubeconfig| kubeconfig.is_managed())
+        .map(|kubeconfig| kubeconfig.name())
+        .collect();
+    let current = names
+        .iter()
+        .position(|name| kubenv.is_current(name))
+        .unwrap_or(0);
+    let selection = FuzzySelect::new()
+        .with_prompt("Apply config")
+        .items(&names)
+        .default(current)
+        .interact_opt();
+
+    return match selection {
+        Ok(index) => Ok(index.map(|i| String::from(names[i]))),
+        Err(dialoguer::Error::IO(msg)) => Err(Error::stream("Cannot read selection", msg)),
+    };
+}
+
+// Without the picker a missing name is a plain usage error
+#[cfg(not(feature = "picker"))]
+fn pick_config(_kubenv: &KubEnv) -> Result<Option<String>> {
+    Cli::command()
+        .error(
+            clap::error::ErrorKind::MissingRequiredArgument,
+            "a config name is required when no default config is set",
+        )
+        .exit();
+}
+
+fn default(kubenv: &KubEnv, name: &Option<String>, unset: bool, quiet: bool) -> Result<ExitCode> {
+    if unset {
+        kubenv.unset_default()?;
+        print_success(quiet, "Default config unset successfully");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    return match name {
+        Some(n) => {
+            kubenv.set_default(n)?;
+            print_success(
+                quiet,
+                &format!("Config '{}' set as default successfully", n),
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        None => match kubenv.default_config() {
+            Some(kubeconfig) => {
+                println!("{}", kubeconfig.name());
+                Ok(ExitCode::SUCCESS)
             }
+            None => Ok(ExitCode::FAILURE),
         },
-        None => BufReader::with_capacity(BUF_SIZE, Box::new(stdin())),
     };
-    kubenv.set_content(name.clone(), &mut reader)?;
-    match name {
-        Some(n) => println!("Import config '{}' successfully", n),
-        None => println!("Import config succesfully"),
+}
+
+fn apply_dry_run(
+    kubenv: &KubEnv,
+    name: &str,
+    link: bool,
+    force: bool,
+    backup: bool,
+) -> Result<ExitCode> {
+    let plan = kubenv.apply_dry_run(name, force, backup)?;
+    let mode = match link {
+        true => "symlink",
+        false => "copy",
+    };
+    let backup = match &plan.backup {
+        Some(path) => path.display().to_string(),
+        None => String::from("-"),
+    };
+    let changed = match plan.changed {
+        true => "yes",
+        false => "no, already applied",
+    };
+    let rows = [
+        ("source", plan.source.display().to_string()),
+        ("destination", plan.destination.display().to_string()),
+        ("mode", String::from(mode)),
+        ("backup", backup),
+        ("changed", String::from(changed)),
+    ];
+    print_rows(&rows);
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn restore(kubenv: &KubEnv, quiet: bool) -> Result<ExitCode> {
+    kubenv.restore_backup()?;
+    print_success(quiet, "Restore config backup successfully");
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+// Redraws a byte counter on stderr at most once per PROGRESS_STEP bytes
+struct ProgressPrinter {
+    written: u64,
+    shown: u64,
+}
+
+impl ProgressPrinter {
+    fn new() -> Self {
+        return Self {
+            written: 0,
+            shown: 0,
+        };
     }
 
-    return Ok(());
+    fn update(&mut self, written: u64) {
+        self.written = written;
+        if written - self.shown >= PROGRESS_STEP {
+            self.shown = written;
+            eprint!("\r{} written", format_size(written));
+        }
+    }
+
+    fn finish(&self) {
+        eprintln!("\r{} written", format_size(self.written));
+    }
 }
 
-fn show(kubenv: &KubEnv, name: &str) -> Result {
-    let mut reader = kubenv.get_content(name)?;
-    let mut writer = stdout().lock();
+fn format_size(bytes: u64) -> String {
+    return match bytes {
+        b if b >= 1 << 20 => format!("{:.1} MiB", b as f64 / (1 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KiB", b as f64 / (1 << 10) as f64),
+        b => format!("{} B", b),
+    };
+}
 
-    reader_to_writer(&mut reader, &mut writer)?;
+fn confirm(question: &str) -> Result<bool> {
+    let mut writer = stderr();
+    if let Err(msg) = write!(writer, "{} [y/N] ", question).and_then(|_| writer.flush()) {
+        return Err(Error::stream("Cannot write", msg));
+    }
+    let mut answer = String::new();
+    if let Err(msg) = stdin().lock().read_line(&mut answer) {
+        return Err(Error::stream("Cannot read answer", msg));
+    }
 
-    return Ok(());
+    return Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"));
 }
 
-fn export(kubenv: &KubEnv, name: &str, path: &PathBuf) -> Result {
-    let mut reader = kubenv.get_content(name)?;
-    let mut writer = match fs::File::create(path) {
-        Ok(f) => f,
-        Err(msg) => match path.to_str() {
-            Some(path) => return Err(format!("Cannot open file '{}': {}", path, msg)),
-            None => return Err(format!("Cannot open file: {}", msg)),
+fn remove(kubenv: &KubEnv, name: &str, by_hash: bool, yes: bool, quiet: bool) -> Result<ExitCode> {
+    let kubeconfig = match by_hash {
+        true => kubenv.find_by_hash_prefix(name)?,
+        false => match kubenv.get_config_by_name(name) {
+            Some(kc) => kc,
+            None => return Err(Error::ConfigNotFound(String::from(name))),
         },
     };
+    if !kubeconfig.is_managed() {
+        return Err(Error::NotManaged(String::from(kubeconfig.name())));
+    }
+    // Scripts without a terminal are not prompted
+    if !yes && stdin().is_terminal() {
+        let question = format!(
+            "Remove config '{}' ({})?",
+            kubeconfig.name(),
+            kubeconfig.path().display(),
+        );
+        if !confirm(&question)? {
+            print_warning("Nothing removed");
+            return Ok(ExitCode::FAILURE);
+        }
+    }
 
-    reader_to_writer(&mut reader, &mut writer)?;
+    kubenv.remove(kubeconfig.name())?;
+    match by_hash {
+        true => print_success(
+            quiet,
+            &format!("Remove config with hash '{}' successfully", name),
+        ),
+        false => print_success(quiet, &format!("Remove config '{}' successfully", name)),
+    }
 
-    println!("Config '{}' exported successfully", name);
-    return Ok(());
+    return Ok(ExitCode::SUCCESS);
+}
+
+// Only TCP connectivity is probed, a server answering with any TLS or HTTP error is reachable
+fn probe(url: &str, timeout: Duration) -> bool {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("https", url));
+    let authority = rest.split('/').next().unwrap_or(rest);
+    let authority = authority.rsplit('@').next().unwrap_or(authority);
+    let default_port: u16 = match scheme {
+        "http" => 80,
+        _ => 443,
+    };
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(ipv6) => match ipv6.split_once(']') {
+            Some((host, port)) => (host, port.strip_prefix(':')),
+            None => return false,
+        },
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let port = match port {
+        Some(p) => match p.parse::<u16>() {
+            Ok(p) => p,
+            Err(_) => return false,
+        },
+        None => default_port,
+    };
+    let addresses = match (host, port).to_socket_addrs() {
+        Ok(a) => a,
+        Err(_) => return false,
+    };
+
+    return addresses
+        .into_iter()
+        .any(|address| TcpStream::connect_timeout(&address, timeout).is_ok());
+}
+
+fn prune(kubenv: &KubEnv, delete: bool, yes: bool, timeout: u64, quiet: bool) -> Result<ExitCode> {
+    let timeout = Duration::from_secs(timeout);
+    let unreachable = kubenv.unreachable(|url| probe(url, timeout));
+    if unreachable.is_empty() {
+        print_success(quiet, "All configs are reachable");
+        return Ok(ExitCode::SUCCESS);
+    }
+    for kubeconfig in &unreachable {
+        println!("{}", kubeconfig.name());
+    }
+    if !delete {
+        return Ok(ExitCode::SUCCESS);
+    }
+    if !yes && stdin().is_terminal() {
+        let question = format!("Remove {} unreachable configs?", unreachable.len());
+        if !confirm(&question)? {
+            print_warning("Nothing removed");
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+
+    for kubeconfig in &unreachable {
+        kubenv.remove(kubeconfig.name())?;
+    }
+    print_success(
+        quiet,
+        &format!(
+            "Removed {} unreachable configs successfully",
+            unreachable.len()
+        ),
+    );
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn rename(kubenv: &mut KubEnv, old: &str, new: &str, quiet: bool) -> Result<ExitCode> {
+    kubenv.rename(old, new)?;
+    print_success(
+        quiet,
+        &format!("Rename config '{}' to '{}' successfully", old, new),
+    );
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn swap(kubenv: &mut KubEnv, first: &str, second: &str, quiet: bool) -> Result<ExitCode> {
+    kubenv.swap(first, second)?;
+    print_success(
+        quiet,
+        &format!("Swap configs '{}' and '{}' successfully", first, second),
+    );
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn add(
+    kubenv: &KubEnv,
+    name: &Option<String>,
+    path: &Option<PathBuf>,
+    context: &Option<String>,
+    validate: bool,
+    overwrite: bool,
+    quiet: bool,
+) -> Result<ExitCode> {
+    let kubeconfig = match (path, context) {
+        (Some(path), None) => kubenv.import_from_path(name.clone(), path, validate, overwrite)?,
+        (Some(path), Some(context)) => {
+            let file = match fs::File::open(path) {
+                Ok(f) => f,
+                Err(msg) => return Err(Error::io("Cannot open file", path, msg)),
+            };
+            let mut reader = BufReader::with_capacity(kubenv.buf_size(), file);
+            kubenv.import_context(name.clone(), &mut reader, context, validate, overwrite)?
+        }
+        // Reading an interactive stdin would block until EOF with no hint to the user
+        (None, _) if stdin().is_terminal() => return Err(Error::NoInput),
+        (None, None) => {
+            let mut reader = BufReader::with_capacity(kubenv.buf_size(), stdin());
+            kubenv.set_content(name.clone(), &mut reader, validate, overwrite)?
+        }
+        (None, Some(context)) => {
+            let mut reader = BufReader::with_capacity(kubenv.buf_size(), stdin());
+            kubenv.import_context(name.clone(), &mut reader, context, validate, overwrite)?
+        }
+    };
+    print_success(
+        quiet,
+        &format!("Import config '{}' successfully", kubeconfig.name()),
+    );
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+#[cfg(feature = "url")]
+fn add_from_url(
+    kubenv: &KubEnv,
+    name: &Option<String>,
+    url: &str,
+    headers: &[String],
+    validate: bool,
+    overwrite: bool,
+    quiet: bool,
+) -> Result<ExitCode> {
+    let kubeconfig = kubenv.import_from_url(name.clone(), url, headers, validate, overwrite)?;
+    print_success(
+        quiet,
+        &format!("Import config '{}' successfully", kubeconfig.name()),
+    );
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+#[cfg(not(feature = "url"))]
+fn add_from_url(
+    _kubenv: &KubEnv,
+    _name: &Option<String>,
+    _url: &str,
+    _headers: &[String],
+    _validate: bool,
+    _overwrite: bool,
+    _quiet: bool,
+) -> Result<ExitCode> {
+    return Err(Error::FeatureDisabled(String::from("url")));
+}
+
+fn clone(kubenv: &KubEnv, source: &str, new_name: &str, quiet: bool) -> Result<ExitCode> {
+    kubenv.clone_config(source, new_name)?;
+    print_success(
+        quiet,
+        &format!("Clone config '{}' to '{}' successfully", source, new_name),
+    );
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn import_dir(kubenv: &mut KubEnv, dir: &Path, validate: bool, quiet: bool) -> Result<ExitCode> {
+    let report = kubenv.import_dir(dir, validate)?;
+    for (path, error) in &report.skipped {
+        print_warning(&format!("Skipped '{}': {}", path.display(), error));
+    }
+    print_success(
+        quiet,
+        &format!(
+            "Imported {} configs, skipped {}",
+            report.imported.len(),
+            report.skipped.len(),
+        ),
+    );
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn alias(
+    kubenv: &KubEnv,
+    alias: &Option<String>,
+    name: &Option<String>,
+    remove: bool,
+    quiet: bool,
+) -> Result<ExitCode> {
+    return match (alias, name) {
+        (Some(alias), _) if remove => {
+            kubenv.remove_alias(alias)?;
+            print_success(quiet, &format!("Remove alias '{}' successfully", alias));
+            Ok(ExitCode::SUCCESS)
+        }
+        (Some(alias), Some(name)) => {
+            kubenv.set_alias(alias, name)?;
+            print_success(
+                quiet,
+                &format!("Alias '{}' set to config '{}' successfully", alias, name),
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        (Some(alias), None) => match kubenv.aliases().into_iter().find(|(a, _)| a == alias) {
+            Some((_, name)) => {
+                println!("{}", name);
+                Ok(ExitCode::SUCCESS)
+            }
+            None => Err(Error::AliasNotFound(alias.clone())),
+        },
+        (None, _) => {
+            for (alias, name) in kubenv.aliases() {
+                println!("{} -> {}", alias, name);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+    };
+}
+
+fn tag(kubenv: &KubEnv, name: &str, tags: &[String], quiet: bool) -> Result<ExitCode> {
+    kubenv.set_tags(name, tags.to_vec())?;
+    print_success(
+        quiet,
+        &format!("Set tags for config '{}' successfully", name),
+    );
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn show(
+    kubenv: &KubEnv,
+    name: &str,
+    pretty: bool,
+    print_hash: bool,
+    verify_hash: &Option<String>,
+) -> Result<ExitCode> {
+    if let Some(expected) = verify_hash {
+        kubenv.check_hash(name, expected)?;
+    }
+    let mut writer = stdout().lock();
+    let mut shown = false;
+    if pretty {
+        match kubenv.get_pretty_content(name) {
+            Ok(content) => {
+                if let Err(msg) = writer.write_all(&content) {
+                    return Err(Error::stream("Cannot write", msg));
+                }
+                shown = true;
+            }
+            Err(Error::Yaml(msg)) => {
+                print_warning(&format!("Cannot parse YAML, showing raw content: {}", msg));
+            }
+            Err(msg) => return Err(msg),
+        }
+    }
+    if !shown {
+        kubenv.export(name, &mut writer)?;
+    }
+    if print_hash {
+        print_hash_of(kubenv, name);
+    }
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+// The hash goes to stderr so it never mixes with the config content on stdout
+fn print_hash_of(kubenv: &KubEnv, name: &str) {
+    if let Some(kubeconfig) = kubenv.get_config_by_name(name) {
+        eprintln!("{}", kubeconfig.hash());
+    }
+}
+
+fn export_all(kubenv: &KubEnv, dir: &Path, quiet: bool) -> Result<ExitCode> {
+    let report = kubenv.export_all(dir)?;
+    print_success(
+        quiet,
+        &format!(
+            "Exported {} configs to '{}' successfully",
+            report.exported,
+            dir.display(),
+        ),
+    );
+    if report.errors.is_empty() {
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    for error in report.errors {
+        print_error(error);
+    }
+    return Ok(ExitCode::FAILURE);
+}
+
+#[cfg(feature = "archive")]
+fn archive(kubenv: &KubEnv, path: &Path, progress: bool, quiet: bool) -> Result<ExitCode> {
+    let count = match progress {
+        true => {
+            let mut printer = ProgressPrinter::new();
+            let count = kubenv.archive_with_progress(path, |written| printer.update(written))?;
+            printer.finish();
+            count
+        }
+        false => kubenv.archive(path)?,
+    };
+    print_success(
+        quiet,
+        &format!(
+            "Archived {} configs to '{}' successfully",
+            count,
+            path.display()
+        ),
+    );
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+#[cfg(feature = "archive")]
+fn unarchive(kubenv: &KubEnv, path: &Path, quiet: bool) -> Result<ExitCode> {
+    let report = kubenv.unarchive(path)?;
+    for skipped in &report.skipped {
+        print_warning(&format!("Skipped '{}'", skipped.display()));
+    }
+    print_success(
+        quiet,
+        &format!(
+            "Extracted {} configs, skipped {}",
+            report.extracted,
+            report.skipped.len(),
+        ),
+    );
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+// Prints the current config name at start and whenever it changes, `-` when
+// no config is detected
+#[cfg(feature = "watch")]
+fn watch(kubenv: &mut KubEnv) -> Result<ExitCode> {
+    let name = |kubenv: &KubEnv| match kubenv.current_config() {
+        Some(kubeconfig) => String::from(kubeconfig.name()),
+        None => String::from("-"),
+    };
+    let mut last = name(kubenv);
+    println!("{}", last);
+    kubenv.watch(|kubenv| {
+        let current = name(kubenv);
+        if current != last {
+            println!("{}", current);
+            // Readers on a pipe expect every change as soon as it happens
+            _ = stdout().flush();
+            last = current;
+        }
+    })?;
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+#[cfg(not(feature = "watch"))]
+fn watch(_kubenv: &mut KubEnv) -> Result<ExitCode> {
+    return Err(Error::FeatureDisabled(String::from("watch")));
+}
+
+#[cfg(not(feature = "archive"))]
+fn archive(_kubenv: &KubEnv, _path: &Path, _progress: bool, _quiet: bool) -> Result<ExitCode> {
+    return Err(Error::FeatureDisabled(String::from("archive")));
+}
+
+#[cfg(not(feature = "archive"))]
+fn unarchive(_kubenv: &KubEnv, _path: &Path, _quiet: bool) -> Result<ExitCode> {
+    return Err(Error::FeatureDisabled(String::from("archive")));
+}
+
+fn verify(kubenv: &KubEnv, name: &Option<String>, all: bool) -> Result<ExitCode> {
+    let names: Vec<&str> = match (name, all) {
+        (_, true) => kubenv.config_names().collect(),
+        (Some(n), false) => vec![n.as_str()],
+        (None, false) => vec![],
+    };
+
+    let mut code = ExitCode::SUCCESS;
+    for name in names {
+        match kubenv.verify(name)? {
+            true => println!("{}: ok", name),
+            false => {
+                println!("{}: modified", name);
+                code = ExitCode::FAILURE;
+            }
+        }
+    }
+
+    return Ok(code);
+}
+
+// Only the exit code is reported so the command reads cleanly in shell conditionals
+fn is_applied(kubenv: &KubEnv, name: &str) -> Result<ExitCode> {
+    return match kubenv.is_applied(name)? {
+        true => Ok(ExitCode::SUCCESS),
+        false => Ok(ExitCode::FAILURE),
+    };
+}
+
+fn edit(kubenv: &mut KubEnv, name: &str, quiet: bool) -> Result<ExitCode> {
+    let path = match kubenv.get_config_by_name(name) {
+        Some(kubeconfig) if kubeconfig.compressed() => {
+            return Err(Error::Compressed {
+                name: String::from(name),
+                action: String::from("edited"),
+            })
+        }
+        Some(kubeconfig) if kubeconfig.encrypted() => {
+            return Err(Error::Encrypted {
+                name: String::from(name),
+                action: String::from("edited"),
+            })
+        }
+        Some(kubeconfig) => kubeconfig.path().to_path_buf(),
+        None => return Err(Error::ConfigNotFound(String::from(name))),
+    };
+    let editor = match env::var("EDITOR") {
+        Ok(value) if !value.trim().is_empty() => value,
+        _ => String::from("vi"),
+    };
+
+    // EDITOR may carry arguments, e.g. "code --wait"
+    let mut args = editor.split_whitespace();
+    let program = args.next().unwrap_or("vi");
+    let status = match Command::new(program).args(args).arg(&path).status() {
+        Ok(s) => s,
+        Err(msg) => {
+            return Err(Error::stream(
+                &format!("Cannot run editor '{}'", editor),
+                msg,
+            ))
+        }
+    };
+    if !status.success() {
+        return Err(Error::Editor { editor, status });
+    }
+
+    kubenv.refresh(name)?;
+    if let Err(msg) = kubenv.validate(name) {
+        print_warning(&format!("{}, keeping the edited file", msg));
+    }
+
+    print_success(quiet, &format!("Config '{}' edited successfully", name));
+    return Ok(ExitCode::SUCCESS);
+}
+
+// With --all the path is a directory, created when missing
+fn export_path(path: &Path, all: bool) -> PathBuf {
+    let message = match (all, path.is_dir()) {
+        (true, false) if path.exists() => "--file must be a directory with --all",
+        (false, true) => "--file must be a file, pass --all to export every config",
+        _ => return path.to_path_buf(),
+    };
+
+    Cli::command()
+        .error(clap::error::ErrorKind::InvalidValue, message)
+        .exit();
+}
+
+fn export(
+    kubenv: &KubEnv,
+    name: &str,
+    path: &Path,
+    print_hash: bool,
+    verify_hash: &Option<String>,
+    progress: bool,
+    quiet: bool,
+) -> Result<ExitCode> {
+    // Checked before the file is created so a mismatch leaves nothing behind
+    if let Some(expected) = verify_hash {
+        kubenv.check_hash(name, expected)?;
+    }
+    // The file is only replaced once the whole config was written
+    match progress {
+        true => {
+            let mut printer = ProgressPrinter::new();
+            kubenv.export_file(name, path, |written| printer.update(written))?;
+            printer.finish();
+        }
+        false => kubenv.export_file(name, path, |_| {})?,
+    }
+    if print_hash {
+        print_hash_of(kubenv, name);
+    }
+
+    print_success(quiet, &format!("Config '{}' exported successfully", name));
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn diff(kubenv: &KubEnv, a: &str, b: &str) -> Result<ExitCode> {
+    let diff = kubenv.diff(a, b)?;
+    if diff.is_empty() {
+        return Ok(ExitCode::SUCCESS);
+    }
+    print!("{}", diff);
+
+    return Ok(ExitCode::FAILURE);
+}
+
+fn contexts(kubenv: &KubEnv, output: OutputFormat, name: &str) -> Result<ExitCode> {
+    let contexts = kubenv.list_contexts(name)?;
+    if output != OutputFormat::Text {
+        let value = json!({
+            "contexts": contexts.names,
+            "current": contexts.current,
+        });
+        print_structured(output, &value)?;
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    for context in &contexts.names {
+        match contexts.current.as_ref() == Some(context) {
+            true => println!("* {}", context),
+            false => println!("  {}", context),
+        }
+    }
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn merge_current(kubenv: &KubEnv, name: &str, quiet: bool) -> Result<ExitCode> {
+    kubenv.merge_into_current(name)?;
+    print_success(
+        quiet,
+        &format!(
+            "Merge config '{}' into '{}' successfully",
+            name,
+            kubenv.config_file().display(),
+        ),
+    );
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn use_context(kubenv: &mut KubEnv, context: &str, quiet: bool) -> Result<ExitCode> {
+    kubenv.set_current_context(context)?;
+    print_success(
+        quiet,
+        &format!("Switch to context '{}' successfully", context),
+    );
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn info(kubenv: &KubEnv, output: OutputFormat, name: &str) -> Result<ExitCode> {
+    let info = kubenv.info(name)?;
+    if output != OutputFormat::Text {
+        let servers: serde_json::Map<String, serde_json::Value> = info
+            .servers
+            .iter()
+            .map(|(cluster, url)| (cluster.clone(), json!(url)))
+            .collect();
+        let value = json!({
+            "clusters": info.clusters,
+            "contexts": info.contexts.names,
+            "current_context": info.contexts.current,
+            "users": info.users,
+            "servers": servers,
+        });
+        print_structured(output, &value)?;
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    println!("clusters:");
+    for cluster in &info.clusters {
+        match info.servers.iter().find(|(name, _)| name == cluster) {
+            Some((_, url)) => println!("  {} {}", cluster, url),
+            None => println!("  {}", cluster),
+        }
+    }
+    println!("contexts:");
+    for context in &info.contexts.names {
+        match info.contexts.current.as_ref() == Some(context) {
+            true => println!("* {}", context),
+            false => println!("  {}", context),
+        }
+    }
+    println!("users:");
+    for user in &info.users {
+        println!("  {}", user);
+    }
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn merge(kubenv: &KubEnv, names: &[String], into: &str, quiet: bool) -> Result<ExitCode> {
+    kubenv.merge(names, into)?;
+    print_success(
+        quiet,
+        &format!("Merge configs into '{}' successfully", into),
+    );
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn duplicates(kubenv: &KubEnv) -> Result<ExitCode> {
+    for (a, b) in kubenv.duplicates() {
+        println!("'{}' and '{}' have the same content", a, b);
+    }
+
+    return Ok(ExitCode::SUCCESS);
+}
+
+fn completions(shell: Shell) -> ExitCode {
+    clap_complete::generate(shell, &mut Cli::command(), "kubenv", &mut stdout());
+
+    return ExitCode::SUCCESS;
 }
@@ -0,0 +1,46 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{write_atomic, Error, Result};
+
+#[derive(Clone, Default, Deserialize, PartialEq, Serialize)]
+pub struct Metadata {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    // Plain content hash of an encrypted config, so it can be listed without the key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+pub fn path(config_path: &Path) -> PathBuf {
+    let mut file_name = match config_path.file_name() {
+        Some(name) => name.to_os_string(),
+        None => OsString::new(),
+    };
+    file_name.push(".meta");
+
+    return config_path.with_file_name(file_name);
+}
+
+pub fn load(config_path: &Path) -> Metadata {
+    let content = match fs::read_to_string(path(config_path)) {
+        Ok(c) => c,
+        Err(_) => return Metadata::default(),
+    };
+
+    return toml::from_str(&content).unwrap_or_default();
+}
+
+pub fn save(config_path: &Path, metadata: &Metadata) -> Result {
+    let meta_path = path(config_path);
+    let content = match toml::to_string(metadata) {
+        Ok(c) => c,
+        Err(msg) => return Err(Error::Metadata(msg.to_string())),
+    };
+    return write_atomic(&meta_path, content.as_bytes());
+}
@@ -0,0 +1,44 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{write_atomic, Error, Result};
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct Settings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+pub fn path(kubenv_dir: &Path) -> PathBuf {
+    return kubenv_dir.join(".settings.toml");
+}
+
+// Settings were kept here before, next to the configs
+fn legacy_path(kubenv_dir: &Path) -> PathBuf {
+    return kubenv_dir.join("kubenv.toml");
+}
+
+pub fn load(kubenv_dir: &Path) -> Settings {
+    let content = match fs::read_to_string(path(kubenv_dir))
+        .or_else(|_| fs::read_to_string(legacy_path(kubenv_dir)))
+    {
+        Ok(c) => c,
+        Err(_) => return Settings::default(),
+    };
+
+    return toml::from_str(&content).unwrap_or_default();
+}
+
+pub fn save(kubenv_dir: &Path, settings: &Settings) -> Result {
+    let settings_path = path(kubenv_dir);
+    let content = match toml::to_string(settings) {
+        Ok(c) => c,
+        Err(msg) => return Err(Error::Metadata(msg.to_string())),
+    };
+    write_atomic(&settings_path, content.as_bytes())?;
+    _ = fs::remove_file(legacy_path(kubenv_dir));
+
+    return Ok(());
+}
@@ -0,0 +1,296 @@
+use serde_yaml::{Mapping, Value};
+
+use crate::{Error, Result};
+
+pub fn parse(content: &[u8]) -> Result<Value> {
+    return match serde_yaml::from_slice(content) {
+        Ok(value) => Ok(value),
+        Err(msg) => Err(Error::Yaml(msg)),
+    };
+}
+
+pub fn validate(content: &[u8]) -> Result {
+    let value: Value = match serde_yaml::from_slice(content) {
+        Ok(v) => v,
+        Err(msg) => return Err(Error::Validation(format!("content is not YAML: {}", msg))),
+    };
+    let mapping = match value.as_mapping() {
+        Some(m) => m,
+        None => return Err(Error::Validation(String::from("content is not a mapping"))),
+    };
+
+    if mapping.get("apiVersion").is_none() {
+        return Err(Error::Validation(String::from("missing 'apiVersion' key")));
+    }
+    match mapping.get("kind").and_then(Value::as_str) {
+        Some("Config") => {}
+        Some(kind) => {
+            return Err(Error::Validation(format!(
+                "'kind' must be 'Config', not '{}'",
+                kind,
+            )))
+        }
+        None => return Err(Error::Validation(String::from("missing 'kind' key"))),
+    }
+    for key in ["clusters", "contexts"] {
+        if sequence(mapping, key).is_empty() {
+            return Err(Error::Validation(format!("no entries under '{}'", key)));
+        }
+    }
+
+    return Ok(());
+}
+
+pub fn sequence<'a>(mapping: &'a Mapping, key: &str) -> &'a [Value] {
+    return match mapping.get(key).and_then(Value::as_sequence) {
+        Some(s) => s,
+        None => &[],
+    };
+}
+
+pub fn names(value: &Value, key: &str) -> Vec<String> {
+    let mapping = match value.as_mapping() {
+        Some(m) => m,
+        None => return vec![],
+    };
+
+    return sequence(mapping, key)
+        .iter()
+        .filter_map(|entry| entry.get("name").and_then(Value::as_str))
+        .map(String::from)
+        .collect();
+}
+
+pub fn server_urls(value: &Value) -> Vec<(String, String)> {
+    let mapping = match value.as_mapping() {
+        Some(m) => m,
+        None => return vec![],
+    };
+
+    return sequence(mapping, "clusters")
+        .iter()
+        .filter_map(|entry| {
+            let name = entry.get("name").and_then(Value::as_str)?;
+            let server = entry
+                .get("cluster")?
+                .get("server")
+                .and_then(Value::as_str)?;
+            return Some((String::from(name), String::from(server)));
+        })
+        .collect();
+}
+
+pub fn current_context(value: &Value) -> Option<String> {
+    return value
+        .get("current-context")
+        .and_then(Value::as_str)
+        .filter(|name| !name.is_empty())
+        .map(String::from);
+}
+
+pub fn set_current_context(value: &mut Value, context: &str) -> Result {
+    if !names(value, "contexts").iter().any(|name| name == context) {
+        return Err(Error::ContextNotFound(String::from(context)));
+    }
+    let mapping = match value.as_mapping_mut() {
+        Some(m) => m,
+        None => return Err(Error::Validation(String::from("expected a mapping"))),
+    };
+    mapping.insert(
+        Value::from("current-context"),
+        Value::from(String::from(context)),
+    );
+
+    return Ok(());
+}
+
+pub fn context_namespace(value: &Value, context: &str) -> Option<String> {
+    let mapping = value.as_mapping()?;
+
+    return sequence(mapping, "contexts")
+        .iter()
+        .find(|entry| entry.get("name").and_then(Value::as_str) == Some(context))?
+        .get("context")?
+        .get("namespace")
+        .and_then(Value::as_str)
+        .filter(|namespace| !namespace.is_empty())
+        .map(String::from);
+}
+
+// Builds a config holding only the context and the cluster and user it refers to
+pub fn extract_context(value: &Value, context: &str) -> Result<Value> {
+    let mapping = match value.as_mapping() {
+        Some(m) => m,
+        None => return Err(Error::Validation(String::from("expected a mapping"))),
+    };
+    let entry = match sequence(mapping, "contexts")
+        .iter()
+        .find(|entry| entry.get("name").and_then(Value::as_str) == Some(context))
+    {
+        Some(e) => e,
+        None => return Err(Error::ContextNotFound(String::from(context))),
+    };
+    let reference = |key: &str| {
+        return entry
+            .get("context")
+            .and_then(|c| c.get(key))
+            .and_then(Value::as_str);
+    };
+    let referenced = |key: &str, name: Option<&str>| -> Vec<Value> {
+        return sequence(mapping, key)
+            .iter()
+            .find(|e| name.is_some() && e.get("name").and_then(Value::as_str) == name)
+            .into_iter()
+            .cloned()
+            .collect();
+    };
+
+    let mut extracted = Mapping::new();
+    for key in ["apiVersion", "kind", "preferences"] {
+        if let Some(v) = mapping.get(key) {
+            extracted.insert(Value::from(key), v.clone());
+        }
+    }
+    extracted.insert(
+        Value::from("clusters"),
+        Value::Sequence(referenced("clusters", reference("cluster"))),
+    );
+    extracted.insert(
+        Value::from("contexts"),
+        Value::Sequence(vec![entry.clone()]),
+    );
+    extracted.insert(
+        Value::from("users"),
+        Value::Sequence(referenced("users", reference("user"))),
+    );
+    extracted.insert(Value::from("current-context"), Value::from(context));
+
+    return Ok(Value::Mapping(extracted));
+}
+
+// Concatenates clusters, contexts and users of the configs. Clusters and users
+// sharing a name are kept once, while a context defined by more than one
+// config is a conflict
+pub fn merge(configs: &[(&str, Value)]) -> Result<Value> {
+    check_entries(configs)?;
+    let mut clusters: Vec<Value> = vec![];
+    let mut contexts: Vec<Value> = vec![];
+    let mut users: Vec<Value> = vec![];
+    let mut context_owners: Vec<(String, &str)> = vec![];
+    let mut current_context: Option<String> = None;
+
+    for (config_name, value) in configs {
+        let mapping = match value.as_mapping() {
+            Some(m) => m,
+            None => continue,
+        };
+        for context in sequence(mapping, "contexts") {
+            let context_name = entry_name(context);
+            if let Some((_, owner)) = context_owners.iter().find(|(n, _)| *n == context_name) {
+                return Err(Error::MergeConflict {
+                    context: context_name,
+                    first: String::from(*owner),
+                    second: String::from(*config_name),
+                });
+            }
+            context_owners.push((context_name, config_name));
+            contexts.push(context.clone());
+        }
+        extend_unique(&mut clusters, sequence(mapping, "clusters"));
+        extend_unique(&mut users, sequence(mapping, "users"));
+        if current_context.is_none() {
+            current_context = self::current_context(value);
+        }
+    }
+
+    let mut merged = Mapping::new();
+    merged.insert(Value::from("apiVersion"), Value::from("v1"));
+    merged.insert(Value::from("kind"), Value::from("Config"));
+    merged.insert(Value::from("clusters"), Value::Sequence(clusters));
+    merged.insert(Value::from("contexts"), Value::Sequence(contexts));
+    merged.insert(Value::from("users"), Value::Sequence(users));
+    if let Some(context) = current_context {
+        merged.insert(Value::from("current-context"), Value::from(context));
+    }
+
+    return Ok(Value::Mapping(merged));
+}
+
+// Clusters or users sharing a name are only combined when they are identical
+fn check_entries(configs: &[(&str, Value)]) -> Result {
+    for (key, kind) in [("clusters", "Cluster"), ("users", "User")] {
+        let mut seen: Vec<(&Value, &str)> = vec![];
+        for (config_name, value) in configs {
+            let mapping = match value.as_mapping() {
+                Some(m) => m,
+                None => continue,
+            };
+            for entry in sequence(mapping, key) {
+                let name = entry.get("name");
+                match seen.iter().find(|(e, _)| e.get("name") == name) {
+                    Some((e, _)) if *e == entry => {}
+                    Some((_, owner)) => {
+                        return Err(Error::EntryConflict {
+                            kind: String::from(kind),
+                            name: entry_name(entry),
+                            first: String::from(*owner),
+                            second: String::from(*config_name),
+                        })
+                    }
+                    None => seen.push((entry, config_name)),
+                }
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+pub fn to_bytes(value: &Value) -> Result<Vec<u8>> {
+    return match serde_yaml::to_string(value) {
+        Ok(content) => Ok(content.into_bytes()),
+        Err(msg) => Err(Error::Yaml(msg)),
+    };
+}
+
+fn entry_name(entry: &Value) -> String {
+    return match entry.get("name").and_then(Value::as_str) {
+        Some(name) => String::from(name),
+        None => String::new(),
+    };
+}
+
+fn extend_unique(entries: &mut Vec<Value>, new_entries: &[Value]) {
+    for entry in new_entries {
+        let name = entry_name(entry);
+        if !entries.iter().any(|e| entry_name(e) == name) {
+            entries.push(entry.clone());
+        }
+    }
+}
+
+// Mapping keys are sorted recursively, so semantically equal configs are
+// serialized the same way regardless of their original formatting
+pub fn canonicalize(value: Value) -> Value {
+    return match value {
+        Value::Mapping(mapping) => {
+            let mut entries: Vec<(Value, Value)> = mapping
+                .into_iter()
+                .map(|(key, value)| (key, canonicalize(value)))
+                .collect();
+            entries.sort_by_key(|(key, _)| key_string(key));
+            Value::Mapping(entries.into_iter().collect())
+        }
+        Value::Sequence(sequence) => {
+            Value::Sequence(sequence.into_iter().map(canonicalize).collect())
+        }
+        other => other,
+    };
+}
+
+fn key_string(key: &Value) -> String {
+    return match key.as_str() {
+        Some(s) => String::from(s),
+        None => serde_yaml::to_string(key).unwrap_or_default(),
+    };
+}